@@ -1,32 +1,64 @@
-pub mod cache;
-pub mod file;
-pub mod serialize;
-
 use std::net::UdpSocket;
 use std::io;
-use serialize::SerialMessage;
-use file::TFile;
-use cache::LRUCache;
+use std::time::{Duration, Instant};
+use common::serialize::{Operation, SerialMessage, Status, PERMISSION_ALL, PERMISSION_READ, PERMISSION_WRITE};
+use common::cache::{BlockCache, CacheEntryInfo, CacheStats, BLOCK_SIZE};
+use common::dir::{self, DirEntry, EntryKind, LIST_PAGE};
+use common::policy::Policy;
+use common::transport::{Faults, Transport};
 
 pub struct Client {
     client_addr: String,
     server_addr: String,
-    client_cache:LRUCache,
-    socket:UdpSocket,
+    //按块缓存读取过的文件
+    client_cache:BlockCache,
+    socket:Transport,
+    //客户端单增的请求编号，每发出一条新请求加一
+    //从随机的编号开始，客户端重启后用同一个地址也不会和上次的编号重复，被服务器当成重传的请求
+    request_id:u32,
+    //等待回复的超时时间，超时后重传请求
+    timeout:Duration,
+    //最多重传的次数
+    max_retries:u32,
+    //缓存的有效期t，距离上次验证不超过t的缓存直接使用
+    freshness:Duration,
+    //登录后得到的会话令牌，之后的请求都带上
+    token:Option<u128>,
 }
 
 impl Client {
     
     //创建Client实例
     pub fn new(client_addr: String, server_addr: String, capacity:i32) -> Self {
+        Client::with_policy(client_addr, server_addr, capacity, Policy::Lru)
+    }
+
+    //创建Client实例，客户端缓存使用指定的淘汰策略
+    pub fn with_policy(client_addr: String, server_addr: String, capacity:i32, policy:Policy) -> Self {
+        Client::with_cache(client_addr, server_addr, BlockCache::with_policy(capacity, BLOCK_SIZE, policy))
+    }
+
+    //创建Client实例，使用已经创建好的客户端缓存，可以指定块大小
+    pub fn with_cache(client_addr: String, server_addr: String, client_cache:BlockCache) -> Self {
         Client {
             client_addr: client_addr.clone(),
-            server_addr: server_addr,
-            client_cache: LRUCache::new(capacity),
-            socket: UdpSocket::bind(&client_addr).unwrap(),
+            server_addr,
+            client_cache,
+            socket: Transport::new(UdpSocket::bind(&client_addr).unwrap()),
+            request_id: Self::initial_request_id(),
+            timeout: Duration::from_secs(1),
+            max_retries: 3,
+            freshness: Duration::from_secs(5),
+            token: None,
         }
     }
 
+    //随机的初始请求编号，RandomState每次创建时使用不同的随机种子
+    fn initial_request_id() -> u32 {
+        use std::hash::{BuildHasher, RandomState};
+        RandomState::new().hash_one(std::process::id()) as u32
+    }
+
     //把客户端和服务端连接
     pub fn connect_server(&self) -> io::Result<()> {
         self.socket.connect(&self.server_addr)?;
@@ -34,8 +66,8 @@ impl Client {
     }
 
     pub fn receive_serial_message(&mut self) -> io::Result<(SerialMessage,String)>{
-        // buf : UDP发送信息的容量
-        let mut buf = [0u8; 1000];
+        // buf : UDP发送信息的容量，一个UDP数据报最大不超过65535字节
+        let mut buf = vec![0u8; 65535];
 
         //amt：UDP具体获得的信息数, src: 客户端地址
         let (amt, src) = self.socket.recv_from(&mut buf)?;
@@ -43,62 +75,523 @@ impl Client {
         //buf 接收的具体长度
         let buf = &mut buf[..amt];
         //self.socket.send_to(buf, &src)?;
-        Ok((SerialMessage::deserialize(buf.to_vec()),src.to_string()))
+        Ok((SerialMessage::deserialize(buf.to_vec())?,src.to_string()))
     }
 
     pub fn send_serial_message(&mut self,serial_message:SerialMessage,address:String) ->io::Result<()> {
         let serial_vec = serial_message.serialize_message();
-        self.socket.send_to(&mut serial_vec.as_slice(), &address)?;
+        self.socket.send_to(serial_vec.as_slice(), &address)?;
         Ok(())
     }
 
-    //远程创建文件
+    //设置模拟的网络故障，outgoing作用于发出的请求，incoming作用于收到的回复
+    pub fn set_faults(&mut self, outgoing:Faults, incoming:Faults, seed:u64) {
+        self.socket.set_faults(outgoing, incoming, seed);
+    }
+
+    //设置等待回复的超时时间和最多重传的次数
+    pub fn set_retransmission(&mut self, timeout:Duration, max_retries:u32) {
+        self.timeout = timeout;
+        self.max_retries = max_retries;
+    }
+
+    //设置缓存的有效期
+    pub fn set_freshness(&mut self, freshness:Duration) {
+        self.freshness = freshness;
+    }
+
+    //接收超时的错误，不同平台上返回的错误类型不同
+    fn is_timeout(e:&io::Error) -> bool {
+        e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+    }
+
+    //发送一条新请求并等待服务器的回复
+    //请求带上新的编号，编号不一致的回复（比如之前请求迟到的回复）直接丢弃
+    //超时没有收到回复就用同一个编号重传，重传max_retries次后仍然没有回复则返回TimedOut错误
+    fn request(&mut self, mut serial_message:SerialMessage) -> io::Result<SerialMessage> {
+        self.request_id = self.request_id.wrapping_add(1);
+        serial_message.set_id(self.request_id);
+        if let Some(token) = self.token {
+            serial_message.set_token(token);
+        }
+        for attempt in 0..=self.max_retries {
+            if attempt > 0 {
+                println!("等待回复超时，第{}次重传请求{}",attempt,self.request_id);
+            }
+            if let Err(err) = self.send_serial_message(serial_message.clone(), self.server_addr.clone()) {
+                println!("网络阻塞，需要重试{}",err);
+                continue;
+            }
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                self.socket.set_read_timeout(Some(deadline - now))?;
+                match self.receive_serial_message() {
+                    Ok((reply, _)) if reply.read_id() == self.request_id => return Ok(reply),
+                    Ok((other, _)) => {
+                        self.handle_push(&other);
+                        continue;
+                    },
+                    Err(e) if Self::is_timeout(&e) => break,
+                    //无法解析的回复直接丢弃，相当于丢包
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut,
+            format!("重传{}次后仍然没有收到服务器的回复",self.max_retries)))
+    }
+
+    //根据回复的状态码判断是否出错，出错的话转换成对应的io::Error
+    //错误信息使用客户端自己的说明，不依赖服务器回复的文字
+    fn check_reply(reply:&SerialMessage) -> io::Result<()> {
+        let kind = match reply.read_status() {
+            Status::Ok => return Ok(()),
+            Status::AlreadyExists => io::ErrorKind::AlreadyExists,
+            Status::NotFound => io::ErrorKind::NotFound,
+            Status::OutOfRange => io::ErrorKind::InvalidInput,
+            Status::PermissionDenied => io::ErrorKind::PermissionDenied,
+            Status::BadRequest => io::ErrorKind::InvalidData,
+        };
+        Err(io::Error::new(kind, reply.read_status().describe()))
+    }
+
+    //处理服务器主动发来的信息，是缓存失效通知时标记对应的缓存失效
+    //文件被删除或者重命名时服务器发来的是删除，把文件移出缓存
+    fn handle_push(&mut self, serial_message:&SerialMessage) -> bool {
+        let name = serial_message.read_name();
+        match serial_message.read_operation() {
+            Operation::Invalidate => {
+                println!("{}在服务器上被修改，缓存失效",name);
+                self.client_cache.expire(&name);
+            },
+            Operation::Delete => {
+                println!("{}在服务器上被删除，移出缓存",name);
+                self.client_cache.invalidate(&name);
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    //处理已经到达但还没读取的服务器推送，不阻塞
+    fn poll_pushes(&mut self) -> io::Result<()> {
+        self.socket.set_nonblocking(true)?;
+        let result = loop {
+            match self.receive_serial_message() {
+                Ok((serial_message, _)) => {
+                    self.handle_push(&serial_message);
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+                Err(e) => break Err(e),
+            }
+        };
+        self.socket.set_nonblocking(false)?;
+        result
+    }
+
+    //确认客户端缓存中的文件是否仍然有效，失效时标记缓存失效，之后的访问重新读取
+    //距离上次验证(Tc)不超过有效期t时直接使用缓存
+    //否则向服务器查询最后修改时间(Tmserver)，和缓存中记录的修改时间(Tmclient)相同则缓存仍然有效，更新Tc
+    //服务器通知过缓存失效的文件直接重新读取
+    fn fresh_file(&mut self, name: String) -> io::Result<()> {
+        self.poll_pushes()?;
+        let validated = match self.client_cache.validated_at(&name) {
+            Some(validated) => validated,
+            None => return Ok(()),
+        };
+        if validated.elapsed() < self.freshness {
+            return Ok(());
+        }
+
+        let send_serial_message = SerialMessage::new(Operation::Modified, 
+                                                        name.clone(), 
+                                                        None, 
+                                                        None, 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(&name, &serial_message)?;
+        if serial_message.read_modified() == self.client_cache.modified(&name) {
+            self.client_cache.revalidate(&name);
+        } else {
+            self.client_cache.expire(&name);
+        }
+        Ok(())
+    }
+
+    //检查和文件有关的回复，服务器上已经没有这个文件时把它移出客户端缓存
+    fn check_file_reply(&mut self, name:&str, serial_message:&SerialMessage) -> io::Result<()> {
+        if serial_message.read_status() == Status::NotFound {
+            self.client_cache.invalidate(name);
+        }
+        Self::check_reply(serial_message)
+    }
+
+    //向服务器读取文件的第index块，返回块的内容和服务器上文件最后修改的时间
+    fn fetch_block(&mut self, name: &str, index: usize) -> io::Result<(Vec<u8>, u64)> {
+        let block_size = self.client_cache.block_size();
+        let send_serial_message = SerialMessage::new(Operation::Read, 
+                                                        name.to_string(), 
+                                                        Some((index * block_size) as u64), 
+                                                        Some(block_size as u64), 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(name, &serial_message)?;
+        let content = serial_message.read_content();
+        let modified = serial_message.read_modified().unwrap_or(0);
+        self.client_cache.put_block(name, index, content.clone(), modified);
+        Ok((content, modified))
+    }
+
+    //客户端缓存的统计数据
+    pub fn cache_stats(&self) -> CacheStats {
+        self.client_cache.stats()
+    }
+
+    //按最近访问的顺序列出客户端缓存的块
+    pub fn cache_snapshot(&self) -> Vec<CacheEntryInfo> {
+        self.client_cache.snapshot()
+    }
+
+    //查询服务器缓存的统计数据
+    pub fn remote_server_stats(&mut self) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::Stats, 
+                                                        String::new(), 
+                                                        None, 
+                                                        None, 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        Self::check_reply(&serial_message)?;
+        Ok(String::from_utf8_lossy(&serial_message.read_content()).to_string())
+    }
+
+    //登录服务器，成功后保存会话令牌，返回令牌的有效期
+    pub fn login(&mut self, user: String, password: String) -> io::Result<Duration> {
+        let send_serial_message = SerialMessage::new(Operation::Login, 
+                                                        user, 
+                                                        None, 
+                                                        None, 
+                                                        None, 
+                                                        Some(password.into_bytes()));
+        let serial_message = self.request(send_serial_message)?;
+        Self::check_reply(&serial_message)?;
+        self.token = serial_message.read_token();
+        Ok(Duration::from_secs(serial_message.read_number().unwrap_or(0)))
+    }
+
+    //退出登录，令牌在服务器上失效
+    pub fn logout(&mut self) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::Logout, String::new(), None, None, None, None);
+        let serial_message = self.request(send_serial_message)?;
+        self.token = None;
+        Self::check_reply(&serial_message)?;
+        Ok("已经退出登录".to_string())
+    }
+
+    //远程创建文件，所有客户端都可以读写
     pub fn remote_create_file(&mut self, name: String,content:Option<String>) -> io::Result<String> {
+        self.remote_create_protected_file(name, content, PERMISSION_ALL, None)
+    }
+
+    //远程创建文件，自己是文件的所有者，others是其他客户端的权限
+    //设置了password时，其他客户端可以用密码解锁文件
+    pub fn remote_create_protected_file(&mut self, name: String, content:Option<String>, others:u64, password:Option<String>) -> io::Result<String> {
         //先从自己的缓存上寻找是否创建过文件
-        if self.client_cache.find(name.clone()) {
+        if self.client_cache.find(&name) {
             return Ok("已经创建过文件".to_string());
         }
 
         //没有则寻求远程创建
         //创建编码并发送
-        let send_serial_message = SerialMessage::new(1, 
+        let send_serial_message = SerialMessage::new(Operation::Create, 
                                                         name.clone(), 
                                                         None, 
-                                                        None, 
-                                                        None, 
-                                                        if content.is_none() {Some("".as_bytes().to_vec())} 
-                                                        else {Some(content.unwrap().as_bytes().to_vec())},);
+                                                        Some(others), 
+                                                        password.map(|password| password.into_bytes()), 
+                                                        Some(content.unwrap_or_default().as_bytes().to_vec()),);
                                                         //{Option("".to_string().to_vec::<u8>())} if content.is_none() else {Option(content.to_vec::<u8>())});
-        let mut res = self.send_serial_message(send_serial_message.clone(), self.server_addr.clone());
-        while let Err(err) = res {
-            println!("网络阻塞，需要重试{}",err);
-            res = self.send_serial_message(send_serial_message.clone(), self.server_addr.clone());
-        }
-        // if let Err(e) = res {
-        //     return Err(e);
-        // }
-        
-        //接收信息
-        let serial_message = self.receive_serial_message()?;
-        if &String::from_utf8(serial_message.0.clone().read_content()).unwrap() == "服务器上已经存在该文件" {
+        let serial_message = self.request(send_serial_message.clone())?;
+        if serial_message.read_status() == Status::AlreadyExists {
             return Ok("已经创建过文件".to_string());
         }
+        Self::check_reply(&serial_message)?;
 
         //如果之前没有创建过，就需要在客户端缓存中创建
         //该操作会把之前最久没使用过的文件挤掉
-        let file = TFile::new_in_client(send_serial_message.read_name(), send_serial_message.read_content()).unwrap();
-        self.client_cache.put(send_serial_message.read_name(), file);    
+        self.client_cache.put_file(&name, send_serial_message.read_content(), serial_message.read_modified().unwrap_or(0));
         Ok("已经成功创建文件".to_string())
     }
 
+    //远程读取文件，从offset开始读取number个字节
+    //客户端按块缓存文件，读取范围覆盖的块中缓存里有的直接使用，只向服务器请求缺少的块
+    //读到比块大小短的块说明到了文件末尾，不再读取后面的块
+    pub fn remote_read_file(&mut self, name: String, offset:u64, number:u64) -> io::Result<String> {
+        let (offset, number) = match (usize::try_from(offset), usize::try_from(number)) {
+            (Ok(offset), Ok(number)) => (offset, number),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, Status::OutOfRange.describe())),
+        };
+        self.fresh_file(name.clone())?;
+        let block_size = self.client_cache.block_size();
+        let first = offset / block_size;
+        let last = offset.saturating_add(number.max(1) - 1) / block_size;
+
+        //读取过程中服务器上的文件被修改时，已经拼好的旧版本内容作废，重新拼接
+        //这一轮的版本是缓存中的版本，缓存中没有这个文件时是这一轮第一次向服务器读到的版本
+        let content = 'read: loop {
+            let mut version = self.client_cache.modified(&name);
+            let mut content = Vec::new();
+            for index in first..=last {
+                let block = match self.client_cache.get_block(&name, index) {
+                    Some(block) => block,
+                    None => {
+                        let (block, modified) = self.fetch_block(&name, index)?;
+                        if *version.get_or_insert(modified) != modified {
+                            continue 'read;
+                        }
+                        block
+                    },
+                };
+                let end = block.len() < block_size;
+                content.extend(block);
+                if end {
+                    break;
+                }
+            }
+            break content;
+        };
+
+        let start = offset - first * block_size;
+        if start > content.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, Status::OutOfRange.describe()));
+        }
+        let end = (start + number).min(content.len());
+        Ok(String::from_utf8_lossy(&content[start..end]).to_string())
+    }
+
+    //远程在offset处插入sequence，返回插入后的文件内容
+    //插入是非幂等操作，回复里只有新的长度，文件可能放不进一个数据报
+    //成功后缓存中的旧内容作废，按块重新读取插入后的文件
+    pub fn remote_insert(&mut self, name: String, offset:u64, sequence:String) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::Insert, 
+                                                        name.clone(), 
+                                                        Some(offset), 
+                                                        None, 
+                                                        Some(sequence.as_bytes().to_vec()), 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(&name, &serial_message)?;
+        self.client_cache.invalidate(&name);
+        let length = serial_message.read_number().unwrap_or(0);
+        self.remote_read_file(name, 0, length)
+    }
+
+    //远程删除文件
+    //非幂等操作，成功后把文件移出客户端缓存
+    pub fn remote_delete_file(&mut self, name: String) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::Delete, 
+                                                        name.clone(), 
+                                                        None, 
+                                                        None, 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(&name, &serial_message)?;
+        self.client_cache.invalidate(&name);
+        Ok("已经成功删除文件".to_string())
+    }
+
+    //远程重命名文件
+    //非幂等操作，成功后旧文件名移出客户端缓存，新文件名在读取时再缓存
+    pub fn remote_rename_file(&mut self, name: String, new_name: String) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::Rename, 
+                                                        name.clone(), 
+                                                        None, 
+                                                        None, 
+                                                        None, 
+                                                        Some(new_name.as_bytes().to_vec()));
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(&name, &serial_message)?;
+        self.client_cache.invalidate(&name);
+        self.client_cache.invalidate(&new_name);
+        Ok("已经成功重命名文件".to_string())
+    }
+
+    //用密码解锁文件，成功后自己有permission中的权限
+    pub fn remote_unlock_file(&mut self, name: String, password: String, permission:u64) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::Unlock, 
+                                                        name.clone(), 
+                                                        None, 
+                                                        Some(permission), 
+                                                        None, 
+                                                        Some(password.into_bytes()));
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(&name, &serial_message)?;
+        Ok("已经成功解锁文件".to_string())
+    }
+
+    //远程创建目录
+    pub fn remote_make_dir(&mut self, name: String) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::MakeDir, name, None, None, None, None);
+        let serial_message = self.request(send_serial_message)?;
+        if serial_message.read_status() == Status::AlreadyExists {
+            return Ok("已经创建过目录".to_string());
+        }
+        Self::check_reply(&serial_message)?;
+        Ok("已经成功创建目录".to_string())
+    }
+
+    //远程删除空目录
+    pub fn remote_remove_dir(&mut self, name: String) -> io::Result<String> {
+        let send_serial_message = SerialMessage::new(Operation::RemoveDir, name, None, None, None, None);
+        let serial_message = self.request(send_serial_message)?;
+        Self::check_reply(&serial_message)?;
+        Ok("已经成功删除目录".to_string())
+    }
+
+    //读取目录从offset开始的一页，返回这一页的目录项和目录的总项数
+    pub fn remote_list_dir_page(&mut self, name: String, offset:usize, number:u64) -> io::Result<(Vec<DirEntry>, usize)> {
+        let send_serial_message = SerialMessage::new(Operation::ListDir, 
+                                                        name, 
+                                                        Some(offset as u64), 
+                                                        Some(number), 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        Self::check_reply(&serial_message)?;
+        let entries = dir::decode_entries(&serial_message.read_content())?;
+        let total = serial_message.read_number().unwrap_or(0) as usize;
+        Ok((entries, total))
+    }
+
+    //列出远程目录的所有项，大目录分成多页请求
+    //name为空时列出服务器的根目录
+    pub fn remote_list_dir(&mut self, name: String) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        loop {
+            let (page, total) = self.remote_list_dir_page(name.clone(), entries.len(), LIST_PAGE)?;
+            let empty = page.is_empty();
+            entries.extend(page);
+            if empty || entries.len() >= total {
+                return Ok(entries);
+            }
+        }
+    }
+
+    //从root开始深度优先遍历远程目录树
+    //返回所有的文件和目录，名字是相对于服务器根目录的路径，每个目录后面紧跟着它的内容
+    pub fn walk_tree(&mut self, root: String) -> io::Result<Vec<DirEntry>> {
+        let mut tree = Vec::new();
+        self.walk_dir(root, &mut tree)?;
+        Ok(tree)
+    }
+
+    fn walk_dir(&mut self, path: String, tree: &mut Vec<DirEntry>) -> io::Result<()> {
+        for mut entry in self.remote_list_dir(path.clone())? {
+            if !path.is_empty() && path != "." {
+                entry.name = format!("{}/{}", path, entry.name);
+            }
+            let subdir = (entry.kind == EntryKind::Dir).then(|| entry.name.clone());
+            tree.push(entry);
+            if let Some(subdir) = subdir {
+                self.walk_dir(subdir, tree)?;
+            }
+        }
+        Ok(())
+    }
+
+    //远程查询文件长度
+    //幂等操作，缓存中有有效的最后一块则直接返回缓存中的长度，不需要读取文件
+    pub fn remote_file_length(&mut self, name: String) -> io::Result<usize> {
+        self.fresh_file(name.clone())?;
+        if let Some(length) = self.client_cache.length(&name) {
+            return Ok(length);
+        }
+
+        let send_serial_message = SerialMessage::new(Operation::Length, 
+                                                        name.clone(), 
+                                                        None, 
+                                                        None, 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        self.check_file_reply(&name, &serial_message)?;
+        match serial_message.read_number() {
+            Some(length) => Ok(length as usize),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "服务器没有返回文件长度")),
+        }
+    }
+
+    //监控远程文件，在duration时间内阻塞等待服务器推送的更新
+    //每收到一次更新就重新读取文件并打印，监控结束后返回所有更新的内容
+    pub fn monitor_file(&mut self, name: String, duration: Duration) -> io::Result<Vec<String>> {
+        let send_serial_message = SerialMessage::new(Operation::Callback, 
+                                                        name.clone(), 
+                                                        None, 
+                                                        Some(duration.as_millis().div_ceil(1000).min(u64::MAX as u128) as u64), 
+                                                        None, 
+                                                        None);
+        let serial_message = self.request(send_serial_message)?;
+        Self::check_reply(&serial_message)?;
+
+        let deadline = Instant::now() + duration;
+        let mut updates = Vec::new();
+        let result = loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break Ok(updates);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            match self.receive_serial_message() {
+                Ok((update, _)) => {
+                    if self.handle_push(&update) {
+                        continue;
+                    }
+                    if update.read_operation() != Operation::Callback || update.read_name() != name {
+                        continue;
+                    }
+                    //推送里只有新的长度，缓存中的旧内容作废，按块重新读取
+                    self.client_cache.invalidate(&name);
+                    let content = self.remote_read_file(name.clone(), 0, update.read_number().unwrap_or(0))?;
+                    println!("{}更新为:{}",name,content);
+                    updates.push(content);
+                },
+                Err(e) if Self::is_timeout(&e) => {
+                    break Ok(updates);
+                },
+                Err(e) => break Err(e),
+            }
+        };
+        result
+    }
 }
 
 fn main() -> std::io::Result<()> {
     let mut client1 = Client::new("127.0.0.1:8081".to_string(),
                                           "127.0.0.1:8080".to_string(),
                                           2);
+    println!("客户端运行在{}",client1.client_addr);
+    //参数drop=概率，模拟收发时按概率丢包
+    if let Some(drop) = std::env::args().find_map(|arg| arg.strip_prefix("drop=").and_then(|p| p.parse().ok())) {
+        client1.set_faults(Faults::lossy(drop), Faults::lossy(drop), 2);
+    }
     //client1.connect_server()?;
     //client1.connect_server()?;
+    //参数user=用户名:密码，服务器开启身份认证时先登录
+    if let Some((user, password)) = std::env::args().find_map(|arg| arg.strip_prefix("user=")
+        .and_then(|user| user.split_once(':')).map(|(user, password)| (user.to_string(), password.to_string()))) {
+        match client1.login(user, password) {
+            Ok(ttl) => println!("已经成功登录，令牌{}秒后过期",ttl.as_secs()),
+            Err(e) => println!("{}",e),
+        }
+    }
 
     let res = client1.remote_create_file("gph.txt".to_string(), Some("gphlzy".to_string()))?;
     println!("{}",res);
@@ -106,7 +599,48 @@ fn main() -> std::io::Result<()> {
     println!("{}",res);
     let res = client1.remote_create_file("g.txt".to_string(), Some("gphlzy".to_string()))?;
     println!("{}",res);
-    println!("{:?}",client1.client_cache.map.keys());
+    println!("{:?}",client1.client_cache.files.keys());
+    match client1.remote_read_file("gph.txt".to_string(), 1, 3) {
+        Ok(content) => println!("{}",content),
+        Err(e) => println!("{}",e),
+    }
+    match client1.remote_insert("gph.txt".to_string(), 3, "-".to_string()) {
+        Ok(content) => println!("{}",content),
+        Err(e) => println!("{}",e),
+    }
+    match client1.remote_file_length("g.txt".to_string()) {
+        Ok(length) => println!("{}",length),
+        Err(e) => println!("{}",e),
+    }
+    match client1.remote_rename_file("g.txt".to_string(), "g2.txt".to_string()) {
+        Ok(res) => println!("{}",res),
+        Err(e) => println!("{}",e),
+    }
+    match client1.remote_delete_file("g2.txt".to_string()) {
+        Ok(res) => println!("{}",res),
+        Err(e) => println!("{}",e),
+    }
+    match client1.remote_make_dir("docs".to_string()) {
+        Ok(res) => println!("{}",res),
+        Err(e) => println!("{}",e),
+    }
+    let _ = client1.remote_create_file("docs/readme.txt".to_string(), Some("readme".to_string()));
+    //其他客户端只能读的文件，用密码解锁后才能修改
+    let _ = client1.remote_create_protected_file("docs/notes.txt".to_string(), Some("notes".to_string()),
+        PERMISSION_READ, Some("gph".to_string()));
+    match client1.remote_unlock_file("docs/notes.txt".to_string(), "gph".to_string(), PERMISSION_WRITE) {
+        Ok(res) => println!("{}",res),
+        Err(e) => println!("{}",e),
+    }
+    match client1.walk_tree(String::new()) {
+        Ok(tree) => tree.iter().for_each(|entry| println!("{}",entry)),
+        Err(e) => println!("{}",e),
+    }
+    println!("客户端缓存：{}",client1.cache_stats());
+    match client1.remote_server_stats() {
+        Ok(stats) => println!("服务器缓存：{}",stats),
+        Err(e) => println!("{}",e),
+    }
     
     loop {
         let mut input = String::new();
@@ -130,3 +664,148 @@ fn main() -> std::io::Result<()> {
         // println!("{:?}",String::from_utf8(SerialMessage::deserialize(buf.to_vec()).read_content()));
     }
 }
+
+#[test]
+fn test_retransmission() {
+    //服务器一直不回复，客户端应该用同一个编号重传后返回超时错误
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let mut client = Client::new("127.0.0.1:0".to_string(), server.local_addr().unwrap().to_string(), 2);
+    client.set_retransmission(Duration::from_millis(50), 2);
+
+    let err = client.remote_file_length("test.txt".to_string()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+    let mut buf = [0u8; 1000];
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        let (amt, _) = server.recv_from(&mut buf).unwrap();
+        let request = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+        ids.push(request.read_id());
+        assert_eq!(request.read_operation(), Operation::Length);
+    }
+    assert!(ids.iter().all(|&id| id == ids[0]));
+}
+
+#[test]
+fn test_cache_freshness() {
+    use std::thread;
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::new("127.0.0.1:0".to_string(), server.local_addr().unwrap().to_string(), 2);
+    //有效期为0，每次读取缓存都要向服务器确认
+    client.set_freshness(Duration::ZERO);
+
+    let handle = thread::spawn(move || {
+        let mut operations = Vec::new();
+        let mut buf = [0u8; 1000];
+        //依次回复：读取，修改时间没变，修改时间变了，重新读取
+        for (modified, content) in [(1, "old"), (1, ""), (2, ""), (2, "new")] {
+            let (amt, src) = server.recv_from(&mut buf).unwrap();
+            let request = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+            operations.push(request.read_operation());
+            let mut reply = SerialMessage::reply(Status::Ok, request.read_name(), Some(content.as_bytes().to_vec()));
+            reply.set_id(request.read_id());
+            reply.set_modified(modified);
+            server.send_to(&reply.serialize_message(), src).unwrap();
+        }
+        operations
+    });
+
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 0, 3).unwrap(), "old");
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 0, 3).unwrap(), "old");
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 0, 3).unwrap(), "new");
+    assert_eq!(handle.join().unwrap(),
+        vec![Operation::Read, Operation::Modified, Operation::Modified, Operation::Read]);
+}
+
+#[test]
+fn test_cache_invalidation() {
+    use std::thread;
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::new("127.0.0.1:0".to_string(), server.local_addr().unwrap().to_string(), 2);
+
+    let handle = thread::spawn(move || {
+        let mut operations = Vec::new();
+        let mut buf = [0u8; 1000];
+        for content in ["old", "new"] {
+            let (amt, src) = server.recv_from(&mut buf).unwrap();
+            let request = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+            operations.push(request.read_operation());
+            let mut reply = SerialMessage::reply(Status::Ok, request.read_name(), Some(content.as_bytes().to_vec()));
+            reply.set_id(request.read_id());
+            server.send_to(&reply.serialize_message(), src).unwrap();
+            //第一次回复之后通知缓存失效
+            if content == "old" {
+                let invalidate = SerialMessage::new(Operation::Invalidate, request.read_name(), None, None, None, None);
+                server.send_to(&invalidate.serialize_message(), src).unwrap();
+            }
+        }
+        operations
+    });
+
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 0, 3).unwrap(), "old");
+    thread::sleep(Duration::from_millis(100));
+    //缓存还在有效期内，但是已经收到失效通知，需要重新读取
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 0, 3).unwrap(), "new");
+    assert_eq!(handle.join().unwrap(), vec![Operation::Read, Operation::Read]);
+}
+
+#[test]
+fn test_block_cache() {
+    use std::thread;
+    use common::file::TFile;
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::with_cache("127.0.0.1:0".to_string(), server.local_addr().unwrap().to_string(), BlockCache::new(8, 4));
+
+    let handle = thread::spawn(move || {
+        let file = TFile::new_in_client("test.txt".to_string(), "abcdefghij".as_bytes().to_vec()).unwrap();
+        let mut offsets = Vec::new();
+        let mut buf = [0u8; 1000];
+        for _ in 0..3 {
+            let (amt, src) = server.recv_from(&mut buf).unwrap();
+            let request = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+            offsets.push(request.read_offset().unwrap());
+            let content = file.read_bytes(request.read_offset().unwrap(), request.read_number());
+            let mut reply = SerialMessage::reply(Status::Ok, request.read_name(), content);
+            reply.set_id(request.read_id());
+            reply.set_modified(1);
+            server.send_to(&reply.serialize_message(), src).unwrap();
+        }
+        offsets
+    });
+
+    //每次只请求缓存中缺少的块
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 5, 3).unwrap(), "fgh");
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 2, 6).unwrap(), "cdefgh");
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 6, 10).unwrap(), "ghij");
+    //缓存了最后一块，长度不需要再请求服务器
+    assert_eq!(client.remote_file_length("test.txt".to_string()).unwrap(), 10);
+    assert_eq!(handle.join().unwrap(), vec![4, 0, 8]);
+}
+
+#[test]
+fn test_read_changed_file() {
+    use std::thread;
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut client = Client::with_cache("127.0.0.1:0".to_string(), server.local_addr().unwrap().to_string(), BlockCache::new(8, 4));
+
+    //读完第0块后服务器上的文件被修改，之后的块都是新版本
+    let handle = thread::spawn(move || {
+        let versions = [(1, "abcdefgh"), (2, "ABCDEFGH"), (2, "ABCDEFGH")];
+        let mut buf = [0u8; 1000];
+        for (modified, content) in versions {
+            let (amt, src) = server.recv_from(&mut buf).unwrap();
+            let request = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+            let offset = request.read_offset().unwrap() as usize;
+            let block = content.as_bytes()[offset..(offset + 4).min(content.len())].to_vec();
+            let mut reply = SerialMessage::reply(Status::Ok, request.read_name(), Some(block));
+            reply.set_id(request.read_id());
+            reply.set_modified(modified);
+            server.send_to(&reply.serialize_message(), src).unwrap();
+        }
+    });
+
+    //没有缓存的文件也不会拼出两个版本混在一起的内容
+    assert_eq!(client.remote_read_file("test.txt".to_string(), 0, 8).unwrap(), "ABCDEFGH");
+    handle.join().unwrap();
+}
@@ -0,0 +1,874 @@
+//假设缓存的大小是有限的，默认利用LRU算法来保证缓存访问的质量，淘汰策略见policy.rs
+use crate::file::{self, TFile};
+use crate::policy::{EvictionPolicy, Policy};
+use crate::serialize::MAX_READ_BYTES;
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+//缓存由哈希表和淘汰策略构成
+//哈希表负责按文件名访问为O(1)
+//满了之后由淘汰策略决定移除哪个文件，默认是LRU，也可以在创建时选择其他策略
+//在服务端没有callout的时候不需要更新数据
+#[derive(Debug)]
+pub struct LRUCache {
+    capacity:i32,
+    //按字节限制容量时的上限，Some时不再按文件个数限制
+    byte_budget:Option<usize>,
+    //缓存中所有文件内容的总字节数
+    bytes:usize,
+    pub map:HashMap<String,CacheEntry>,
+    policy:Box<dyn EvictionPolicy>,
+    //命中、未命中、淘汰和放入的次数
+    stats:CacheStats,
+    //逻辑时钟，每次放入或访问加一，用来按最近访问的顺序列出文件
+    clock:u64,
+}
+
+//缓存的一项由TFile和验证时间构成
+//TFile包含了名字，长度和储存的信息，是客户端的缓存，便于客户端重复访问数据
+#[derive(Debug)]
+pub struct CacheEntry {
+    value:file::TFile,
+    //上一次确认缓存有效的时间，放入缓存时就是有效的
+    //None表示已经知道缓存失效，下次访问需要重新读取
+    validated:Option<Instant>,
+    //放入缓存后被访问的次数
+    accesses:u64,
+    //最近一次放入或访问时的逻辑时钟
+    last_used:u64,
+}
+
+//缓存的统计数据
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits:u64,
+    pub misses:u64,
+    pub evictions:u64,
+    pub insertions:u64,
+    //缓存中文件的数量和内容的总字节数
+    pub entries:usize,
+    pub bytes:usize,
+    //还没有写回磁盘的文件数量
+    pub dirty:usize,
+}
+
+impl CacheStats {
+    //命中率，没有访问过时为0
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "命中{}次，未命中{}次，命中率{:.1}%，淘汰{}次，放入{}次，缓存{}个文件共{}字节，{}个文件未写回",
+            self.hits, self.misses, self.hit_rate() * 100.0, self.evictions, self.insertions, self.entries, self.bytes, self.dirty)
+    }
+}
+
+//缓存中一个文件的快照
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntryInfo {
+    pub key:String,
+    pub length:usize,
+    //放入缓存后被访问的次数
+    pub accesses:u64,
+    //缓存是否仍然有效
+    pub valid:bool,
+}
+
+impl fmt::Display for CacheEntryInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}字节 访问{}次{}", self.key, self.length, self.accesses, if self.valid { "" } else { " 已失效" })
+    }
+}
+
+
+//相关的操作的实现
+//其中new,get,put以及invalidate,clear,retain,pop_lru是可以被外界访问的API
+impl LRUCache {
+
+    //创建缓存区操作，capacity是最多缓存的文件个数
+    pub fn new(capacity: i32) -> Self {
+        LRUCache::with_policy(capacity, Policy::Lru)
+    }
+
+    //创建使用指定淘汰策略的缓存区
+    pub fn with_policy(capacity: i32, policy: Policy) -> Self {
+        LRUCache {
+            capacity,
+            byte_budget:None,
+            bytes:0,
+            map:HashMap::new(),
+            policy:policy.build(),
+            stats:CacheStats::default(),
+            clock:0,
+        }
+    }
+
+    //创建按字节限制容量的缓存区，所有文件内容加起来不超过budget字节
+    pub fn with_byte_budget(budget: usize) -> Self {
+        LRUCache {
+            byte_budget:Some(budget),
+            ..LRUCache::new(i32::MAX)
+        }
+    }
+
+    //更换淘汰策略，已经缓存的文件按原来的淘汰顺序交给新的策略
+    pub fn set_policy(&mut self, policy: Policy) {
+        let mut order = Vec::new();
+        while let Some(key) = self.policy.evict() {
+            order.push(key);
+        }
+        self.policy = policy.build();
+        for key in order {
+            self.policy.on_insert(&key);
+        }
+    }
+
+    //缓存中所有文件内容的总字节数
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+    
+    //统计数据，包括当前缓存的文件数量和字节数
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries:self.map.len(),
+            bytes:self.bytes,
+            dirty:self.map.values().filter(|entry| entry.value.is_dirty()).count(),
+            ..self.stats
+        }
+    }
+
+    //清空命中、未命中、淘汰和放入的次数
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    //文件放入缓存后被访问的次数，不在缓存中时返回None
+    pub fn access_count(&self, key:&str) -> Option<u64> {
+        self.map.get(key).map(|entry| entry.accesses)
+    }
+
+    //按最近访问的顺序列出缓存中的文件，最近访问的在前面
+    pub fn snapshot(&self) -> Vec<CacheEntryInfo> {
+        let mut entries:Vec<(&String, &CacheEntry)> = self.map.iter().collect();
+        entries.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.last_used));
+        entries.into_iter().map(|(key, entry)| CacheEntryInfo {
+            key:key.clone(),
+            length:entry.value.read_length(),
+            accesses:entry.accesses,
+            valid:entry.validated.is_some(),
+        }).collect()
+    }
+    
+    //寻找缓存区是否有文件，有的话进行访问
+    pub fn get(&mut self, key: String) -> Result<TFile,&'static str> {
+        if let Some(entry) = self.map.get_mut(&key) {
+            self.clock += 1;
+            entry.accesses += 1;
+            entry.last_used = self.clock;
+            self.stats.hits += 1;
+            let value = entry.value.clone();
+            self.policy.on_access(&key);
+            Ok(value)
+        }else {
+            self.stats.misses += 1;
+            Err("Don't find in cache")
+        }
+    }
+    
+    //查找文件是否在缓存区存在
+    pub fn find(&self,name:String) -> bool {
+        self.map.contains_key(&name)
+    }
+
+    //查看缓存中的文件，不改变访问顺序，也不计入统计
+    pub fn peek(&self, key:&str) -> Option<&TFile> {
+        self.map.get(key).map(|entry| &entry.value)
+    }
+
+    //把所有还没有写回的文件写到磁盘上，返回写回的文件数
+    //某个文件写回失败时继续写其他文件，最后返回第一个错误
+    pub fn flush(&mut self) -> std::io::Result<usize> {
+        self.flush_where(|_| true)
+    }
+
+    //只写回predicate返回true的文件，其他文件仍然留在缓存中等待写回
+    pub fn flush_where<F>(&mut self, predicate: F) -> std::io::Result<usize> where F: Fn(&str) -> bool {
+        let mut flushed = 0;
+        let mut result = Ok(());
+        for (_, entry) in self.map.iter_mut().filter(|(key, entry)| entry.value.is_dirty() && predicate(key)) {
+            match entry.value.flush() {
+                Ok(()) => flushed += 1,
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                },
+            }
+        }
+        result.map(|_| flushed)
+    }
+
+    //读取文件上一次确认有效的时间，不改变访问顺序
+    //没有该文件或者已经失效时返回None
+    pub fn validated_at(&self, key:String) -> Option<Instant> {
+        self.map.get(&key).and_then(|entry| entry.validated)
+    }
+
+    //向服务器确认文件没有被修改后，把验证时间更新为现在
+    pub fn revalidate(&mut self, key:String) {
+        if let Some(entry) = self.map.get_mut(&key) {
+            entry.validated = Some(Instant::now());
+        }
+    }
+
+    //标记文件已经失效，文件还留在缓存中，下次访问时重新读取
+    pub fn expire(&mut self, key:String) {
+        if let Some(entry) = self.map.get_mut(&key) {
+            entry.validated = None;
+        }
+    }
+
+    //向缓存区添加数据，如果满了则按淘汰策略移除文件
+    //同时也可以更新数据，如果已经存在该文件，则直接更新
+    //按字节限制时移除尽可能少的文件直到放得下，比整个缓存还大的文件不放入缓存
+    //返回文件是否放入了缓存
+    pub fn put(&mut self, key: String, value: TFile) -> bool {
+        let size = value.read_length();
+        self.remove(&key);
+        match self.byte_budget {
+            Some(budget) => {
+                //旧的内容已经移除，放不下新内容时缓存中不再有该文件
+                if size > budget {
+                    return false;
+                }
+                while self.bytes + size > budget {
+                    self.evict();
+                }
+            },
+            None => {
+                if self.map.len() >= self.capacity as usize {
+                    self.evict();
+                }
+            },
+        }
+        self.bytes += size;
+        self.clock += 1;
+        self.stats.insertions += 1;
+        self.policy.on_insert(&key);
+        self.map.insert(key, CacheEntry {
+            value,
+            validated:Some(Instant::now()),
+            accesses:0,
+            last_used:self.clock,
+        });
+        true
+    }
+
+    //把文件移出缓存，文件被删除或者已经知道被修改时使用，返回移除的文件
+    //没有写回的修改直接丢弃，需要保留时由调用者处理返回的文件
+    pub fn invalidate(&mut self, key:&str) -> Option<TFile> {
+        self.remove(key)
+    }
+
+    //清空缓存，统计数据保留，还没有写回的文件先写回磁盘
+    pub fn clear(&mut self) {
+        if let Err(e) = self.flush() {
+            println!("清空缓存时写回文件失败:{}",e);
+        }
+        self.map.clear();
+        self.bytes = 0;
+        self.policy.clear();
+    }
+
+    //只保留predicate返回true的文件，移除的文件和invalidate一样不写回
+    pub fn retain<F>(&mut self, mut predicate: F) where F: FnMut(&str, &TFile) -> bool {
+        let removed:Vec<String> = self.map.iter()
+            .filter(|(key, entry)| !predicate(key, &entry.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in removed {
+            self.remove(&key);
+        }
+    }
+
+    //移除并返回下一个要淘汰的文件，默认的LRU策略下就是最久没访问的文件
+    pub fn pop_lru(&mut self) -> Option<(String, TFile)> {
+        self.evict()
+    }
+
+    //移除文件操作
+    fn remove(&mut self, key:&str) -> Option<TFile> {
+        let entry = self.map.remove(key)?;
+        self.bytes -= entry.value.read_length();
+        self.policy.on_remove(key);
+        Some(entry.value)
+    }
+
+    //按淘汰策略移除一个文件（满了就需要移除），还没有写回的文件先写回磁盘
+    fn evict(&mut self) -> Option<(String, TFile)> {
+        let key = self.policy.evict()?;
+        let mut entry = self.map.remove(&key)?;
+        self.bytes -= entry.value.read_length();
+        self.stats.evictions += 1;
+        if let Err(e) = entry.value.flush() {
+            println!("淘汰{}时写回文件失败:{}",key,e);
+        }
+        Some((key, entry.value))
+    }
+}
+
+
+//按块缓存文件
+//客户端通常只读取文件的一部分，缓存整个文件既浪费内存也浪费带宽
+//文件按固定大小分块，以(文件名,块号)为key放进LRUCache，读取时只向服务器请求缺少的块
+//比块大小短的块是文件的最后一块，缓存了最后一块就知道文件的长度
+//有效期和修改时间按文件记录，块的修改时间和文件的不同说明是旧版本的块，不再使用
+#[derive(Debug)]
+pub struct BlockCache {
+    block_size:usize,
+    blocks:LRUCache,
+    pub files:HashMap<String,BlockFile>,
+}
+
+//缓存中一个文件的状态
+#[derive(Debug)]
+pub struct BlockFile {
+    //服务器上的最后修改时间
+    modified:u64,
+    //已经缓存了最后一块时记录文件长度
+    length:Option<usize>,
+    //上一次确认缓存有效的时间，None表示已经知道缓存失效
+    validated:Option<Instant>,
+}
+
+//默认的块大小
+pub const BLOCK_SIZE:usize = 1024;
+
+impl BlockCache {
+
+    //capacity是最多缓存的块数
+    pub fn new(capacity: i32, block_size: usize) -> Self {
+        BlockCache::with_policy(capacity, block_size, Policy::Lru)
+    }
+
+    pub fn with_policy(capacity: i32, block_size: usize, policy: Policy) -> Self {
+        BlockCache {
+            //块不能超过一次读取回复的大小，否则服务器只回复一部分，会被当成文件的最后一块
+            block_size:block_size.clamp(1, MAX_READ_BYTES as usize),
+            blocks:LRUCache::with_policy(capacity, policy),
+            files:HashMap::new(),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    //块在LRUCache中的key，文件名中不会出现'\0'
+    fn block_key(name:&str, index:usize) -> String {
+        format!("{}\0{}", name, index)
+    }
+
+    //查找文件是否在缓存区存在
+    pub fn find(&self, name:&str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    //读取文件上一次确认有效的时间，没有该文件或者已经失效时返回None
+    pub fn validated_at(&self, name:&str) -> Option<Instant> {
+        self.files.get(name).and_then(|file| file.validated)
+    }
+
+    //缓存中记录的文件修改时间
+    pub fn modified(&self, name:&str) -> Option<u64> {
+        self.files.get(name).map(|file| file.modified)
+    }
+
+    //向服务器确认文件没有被修改后，把验证时间更新为现在
+    pub fn revalidate(&mut self, name:&str) {
+        if let Some(file) = self.files.get_mut(name) {
+            file.validated = Some(Instant::now());
+        }
+    }
+
+    //标记文件的所有块已经失效，下次访问时重新读取
+    pub fn expire(&mut self, name:&str) {
+        if let Some(file) = self.files.get_mut(name) {
+            file.validated = None;
+        }
+    }
+
+    //把文件的所有块移出缓存，文件在服务器上被删除时使用
+    pub fn invalidate(&mut self, name:&str) {
+        let prefix = format!("{}\0", name);
+        self.blocks.retain(|key, _| !key.starts_with(&prefix));
+        self.files.remove(name);
+    }
+
+    //清空缓存
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.files.clear();
+    }
+
+    //缓存仍然有效时返回文件长度，没有缓存最后一块时返回None
+    pub fn length(&self, name:&str) -> Option<usize> {
+        self.files.get(name).filter(|file| file.validated.is_some()).and_then(|file| file.length)
+    }
+
+    //取出仍然有效的块，失效或者旧版本的块也算作未命中
+    pub fn get_block(&mut self, name:&str, index:usize) -> Option<Vec<u8>> {
+        let key = Self::block_key(name, index);
+        let valid = match (self.files.get(name), self.blocks.map.get(&key)) {
+            (Some(file), Some(entry)) => file.validated.is_some() && entry.value.read_modified() == file.modified,
+            _ => false,
+        };
+        if !valid {
+            self.blocks.stats.misses += 1;
+            return None;
+        }
+        self.blocks.get(key).ok().map(|block| block.read_content())
+    }
+
+    //块的统计数据
+    pub fn stats(&self) -> CacheStats {
+        self.blocks.stats()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.blocks.reset_stats();
+    }
+
+    //按最近访问的顺序列出缓存中的块，块的名字是"文件名#块号"
+    pub fn snapshot(&self) -> Vec<CacheEntryInfo> {
+        let mut entries = self.blocks.snapshot();
+        for entry in entries.iter_mut() {
+            entry.key = entry.key.replacen('\0', "#", 1);
+        }
+        entries
+    }
+
+    //放入从服务器读取的一块，modified是服务器返回的修改时间
+    //修改时间和缓存中的不同时，之前缓存的块全部作废
+    pub fn put_block(&mut self, name:&str, index:usize, content:Vec<u8>, modified:u64) {
+        let block_size = self.block_size;
+        let file = self.files.entry(name.to_string()).or_insert(BlockFile {
+            modified,
+            length:None,
+            validated:None,
+        });
+        if file.modified != modified {
+            file.modified = modified;
+            file.length = None;
+        }
+        file.validated = Some(Instant::now());
+        if content.len() < block_size {
+            file.length = Some(index * block_size + content.len());
+        }
+        if let Ok(mut block) = TFile::new_in_client(name.to_string(), content) {
+            block.set_modified(modified);
+            self.blocks.put(Self::block_key(name, index), block);
+        }
+    }
+
+    //放入整个文件，按块大小切开
+    //长度正好是块大小的整数倍时，最后再放一个空块表示文件结束
+    pub fn put_file(&mut self, name:&str, content:Vec<u8>, modified:u64) {
+        let mut blocks:Vec<&[u8]> = content.chunks(self.block_size).collect();
+        if content.len().is_multiple_of(self.block_size) {
+            blocks.push(&[]);
+        }
+        for (index, block) in blocks.into_iter().enumerate() {
+            self.put_block(name, index, block.to_vec(), modified);
+        }
+    }
+}
+
+
+//线程安全的LRU缓存
+//LRUCache的节点用Rc<RefCell>连接，不能在线程之间共享
+//这里的链表用数组下标连接（slab），不需要Rc，再用Mutex保护，可以放进Arc给多个线程使用
+//按key的哈希分成多个分片，每个分片一把锁，减少线程之间的竞争
+//分片之间互相独立，每个分片各自按LRU淘汰，只有一个分片时就是严格的LRU
+#[derive(Debug)]
+pub struct ConcurrentLRUCache {
+    shards:Vec<Mutex<SlabLRU>>,
+}
+
+//数组中的一个节点，prev和next是相邻节点的下标
+#[derive(Debug)]
+struct Slot {
+    key:String,
+    value:TFile,
+    prev:Option<usize>,
+    next:Option<usize>,
+}
+
+//用数组实现的LRU，被删除的位置放进free里重复使用
+#[derive(Debug)]
+struct SlabLRU {
+    capacity:usize,
+    map:HashMap<String,usize>,
+    slots:Vec<Option<Slot>>,
+    free:Vec<usize>,
+    first:Option<usize>,
+    last:Option<usize>,
+}
+
+impl ConcurrentLRUCache {
+
+    //只有一个分片的缓存
+    pub fn new(capacity: i32) -> Self {
+        ConcurrentLRUCache::with_shards(capacity, 1)
+    }
+
+    //容量平均分给每个分片，除不尽的部分前面的分片各多分一个，所有分片加起来正好是capacity
+    pub fn with_shards(capacity: i32, shards: usize) -> Self {
+        let shards = shards.max(1);
+        let capacity = capacity.max(0) as usize;
+        let (each, extra) = (capacity / shards, capacity % shards);
+        ConcurrentLRUCache {
+            shards:(0..shards).map(|i| Mutex::new(SlabLRU::new(each + usize::from(i < extra)))).collect(),
+        }
+    }
+
+    fn shard(&self, key:&str) -> MutexGuard<'_, SlabLRU> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = hasher.finish() as usize % self.shards.len();
+        //持有锁的线程panic不会破坏链表的结构，可以继续使用
+        self.shards[index].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    //寻找缓存区是否有文件，有的话进行访问
+    pub fn get(&self, key: String) -> Result<TFile,&'static str> {
+        self.shard(&key).get(&key).ok_or("Don't find in cache")
+    }
+
+    //查找文件是否在缓存区存在
+    pub fn find(&self, name:String) -> bool {
+        self.shard(&name).map.contains_key(&name)
+    }
+
+    //向缓存区添加数据，如果满了则移除最久没访问的文件
+    pub fn put(&self, key: String, value: TFile) {
+        self.shard(&key).put(key, value);
+    }
+
+    //缓存中文件的数量
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap_or_else(|e| e.into_inner()).map.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SlabLRU {
+
+    fn new(capacity:usize) -> Self {
+        SlabLRU {
+            capacity,
+            map:HashMap::new(),
+            slots:Vec::new(),
+            free:Vec::new(),
+            first:None,
+            last:None,
+        }
+    }
+
+    fn slot(&mut self, index:usize) -> &mut Slot {
+        self.slots[index].as_mut().expect("链表中的下标一定有节点")
+    }
+
+    fn get(&mut self, key:&str) -> Option<TFile> {
+        let index = *self.map.get(key)?;
+        self.unlink(index);
+        self.push_last(index);
+        Some(self.slot(index).value.clone())
+    }
+
+    fn put(&mut self, key:String, value:TFile) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(&index) = self.map.get(&key) {
+            self.slot(index).value = value;
+            self.unlink(index);
+            self.push_last(index);
+            return;
+        }
+        if self.map.len() == self.capacity {
+            self.poll_first();
+        }
+        let slot = Slot { key:key.clone(), value, prev:None, next:None };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index] = Some(slot);
+                index
+            },
+            None => {
+                self.slots.push(Some(slot));
+                self.slots.len() - 1
+            },
+        };
+        self.map.insert(key, index);
+        self.push_last(index);
+    }
+
+    //把节点从链表中摘下来，节点还留在数组里
+    fn unlink(&mut self, index:usize) {
+        let (prev, next) = {
+            let slot = self.slot(index);
+            (slot.prev.take(), slot.next.take())
+        };
+        match prev {
+            Some(prev) => self.slot(prev).next = next,
+            None => self.first = next,
+        }
+        match next {
+            Some(next) => self.slot(next).prev = prev,
+            None => self.last = prev,
+        }
+    }
+
+    //把节点放到链表的末尾（最新访问区）
+    fn push_last(&mut self, index:usize) {
+        self.slot(index).prev = self.last;
+        match self.last {
+            Some(last) => self.slot(last).next = Some(index),
+            None => self.first = Some(index),
+        }
+        self.last = Some(index);
+    }
+
+    //移除链表的第一个元素（满了就需要移除）
+    fn poll_first(&mut self) {
+        if let Some(index) = self.first {
+            self.unlink(index);
+            if let Some(slot) = self.slots[index].take() {
+                self.map.remove(&slot.key);
+            }
+            self.free.push(index);
+        }
+    }
+}
+
+
+//测试区代码
+#[test]
+fn test() -> std::io::Result<()> {
+    let mut lru = LRUCache::new(2);
+    let my_string = String::from("Hello, Rust!");
+    let my_string2 = String::from("hello,rust!");
+    let my_string3 = String::from("lzy");
+    // 将字符串转换为 Vec<u8>
+    let bytes = my_string.into_bytes();
+    let bytes2 = my_string2.into_bytes();
+    let bytes3 = my_string3.into_bytes();
+
+    let t = TFile::new("test1.txt".to_string(),bytes)?;
+    let t2 = TFile::new("test2.txt".to_string(),bytes2)?;
+    let t3 = TFile::new("test3.txt".to_string(),bytes3)?;
+
+    lru.put("1".to_string(),t);
+
+    //理论上来讲，此时cache中不能含有2
+    let b = lru.get("2".to_string());
+    lru.put("2".to_string(),t2);
+    let a = lru.get("1".to_string()).unwrap();
+
+    //由于只有2个容量，所以cache保存的是最近访问的1和3，其中还是没有2，所以访问2还是找不到
+    lru.put("3".to_string(),t3);
+    let c = lru.get("2".to_string());
+    println!("{:?}",a.read_tfile());
+    println!("{:?}",b);
+    println!("{:?}",c);
+    Ok(())
+}
+
+#[test]
+fn test_concurrent() {
+    use std::sync::Arc;
+    use std::thread;
+
+    //和LRUCache的行为相同
+    let lru = ConcurrentLRUCache::new(2);
+    let file = |content:&str| TFile::new_in_client("test".to_string(), content.as_bytes().to_vec()).unwrap();
+    lru.put("1".to_string(), file("1"));
+    lru.put("2".to_string(), file("2"));
+    assert!(lru.get("1".to_string()).is_ok());
+    lru.put("3".to_string(), file("3"));
+    assert!(lru.get("2".to_string()).is_err());
+    assert!(lru.find("1".to_string()) && lru.find("3".to_string()));
+    lru.put("1".to_string(), file("new"));
+    assert_eq!(lru.get("1".to_string()).unwrap().read_content(), "new".as_bytes().to_vec());
+
+    //多个线程共享同一个缓存
+    let lru = Arc::new(ConcurrentLRUCache::with_shards(64, 4));
+    let handles:Vec<_> = (0..4).map(|t| {
+        let lru = Arc::clone(&lru);
+        thread::spawn(move || {
+            for i in 0..100 {
+                let key = format!("{}-{}", t, i);
+                lru.put(key.clone(), TFile::new_in_client(key.clone(), vec![t as u8]).unwrap());
+                let _ = lru.get(key);
+            }
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(lru.len(), 64);
+
+    //分片数比容量多时，总数仍然不超过容量
+    let lru = ConcurrentLRUCache::with_shards(2, 4);
+    for i in 0..20 {
+        lru.put(i.to_string(), file("x"));
+    }
+    assert!(lru.len() <= 2);
+}
+
+#[test]
+fn test_byte_budget() {
+    let file = |size:usize| TFile::new_in_client("test".to_string(), vec![0; size]).unwrap();
+    let mut lru = LRUCache::with_byte_budget(10);
+    assert!(lru.put("1".to_string(), file(4)));
+    assert!(lru.put("2".to_string(), file(4)));
+    assert_eq!(lru.bytes(), 8);
+
+    //放入6字节需要移除最久没访问的1和2
+    let _ = lru.get("1".to_string());
+    assert!(lru.put("3".to_string(), file(6)));
+    assert!(!lru.find("2".to_string()));
+    assert!(lru.find("1".to_string()));
+    assert_eq!(lru.bytes(), 10);
+
+    //更新已有的文件时按新的大小计算
+    assert!(lru.put("3".to_string(), file(2)));
+    assert_eq!(lru.bytes(), 6);
+
+    //比整个缓存还大的文件不放入缓存，旧的内容也不再保留
+    assert!(!lru.put("1".to_string(), file(11)));
+    assert!(!lru.find("1".to_string()));
+    assert_eq!(lru.bytes(), 2);
+}
+
+#[test]
+fn test_policy() {
+    let file = |content:&str| TFile::new_in_client("test".to_string(), content.as_bytes().to_vec()).unwrap();
+    //LFU：1被访问过，放入3时淘汰2
+    let mut lfu = LRUCache::with_policy(2, Policy::Lfu);
+    lfu.put("1".to_string(), file("1"));
+    lfu.put("2".to_string(), file("2"));
+    let _ = lfu.get("1".to_string());
+    lfu.put("3".to_string(), file("3"));
+    assert!(lfu.find("1".to_string()) && !lfu.find("2".to_string()));
+
+    //FIFO：访问不改变顺序，放入3时淘汰最早放入的1
+    let mut fifo = LRUCache::with_policy(2, Policy::Fifo);
+    fifo.put("1".to_string(), file("1"));
+    fifo.put("2".to_string(), file("2"));
+    let _ = fifo.get("1".to_string());
+    fifo.put("3".to_string(), file("3"));
+    assert!(!fifo.find("1".to_string()) && fifo.find("2".to_string()));
+
+    //更换策略后保留原来的淘汰顺序
+    fifo.set_policy(Policy::Lru);
+    fifo.put("4".to_string(), file("4"));
+    assert!(!fifo.find("2".to_string()) && fifo.find("3".to_string()));
+}
+
+#[test]
+fn test_block_cache() {
+    let mut cache = BlockCache::new(8, 4);
+    cache.put_file("test", "abcdefgh".as_bytes().to_vec(), 1);
+    assert_eq!(cache.get_block("test", 1), Some("efgh".as_bytes().to_vec()));
+    //长度是块大小的整数倍，最后有一个空块
+    assert_eq!(cache.get_block("test", 2), Some(Vec::new()));
+    assert_eq!(cache.length("test"), Some(8));
+
+    //服务器上的文件被修改，旧版本的块不再使用
+    cache.put_block("test", 0, "ABCD".as_bytes().to_vec(), 2);
+    assert_eq!(cache.get_block("test", 0), Some("ABCD".as_bytes().to_vec()));
+    assert_eq!(cache.get_block("test", 1), None);
+    assert_eq!(cache.length("test"), None);
+
+    cache.expire("test");
+    assert_eq!(cache.get_block("test", 0), None);
+    assert!(cache.find("test"));
+
+    //文件被删除后所有的块都移出缓存
+    cache.put_file("other", "xyz".as_bytes().to_vec(), 1);
+    cache.invalidate("test");
+    assert!(!cache.find("test"));
+    assert_eq!(cache.stats().entries, 1);
+}
+
+#[test]
+fn test_stats() {
+    let file = |content:&str| TFile::new_in_client("test".to_string(), content.as_bytes().to_vec()).unwrap();
+    let mut lru = LRUCache::new(2);
+    lru.put("1".to_string(), file("a"));
+    lru.put("2".to_string(), file("bc"));
+    let _ = lru.get("1".to_string());
+    let _ = lru.get("1".to_string());
+    let _ = lru.get("3".to_string());
+    lru.put("3".to_string(), file("def"));
+
+    let stats = lru.stats();
+    assert_eq!((stats.hits, stats.misses, stats.evictions, stats.insertions), (2, 1, 1, 3));
+    assert_eq!((stats.entries, stats.bytes), (2, 4));
+    assert_eq!(lru.access_count("1"), Some(2));
+
+    //最近访问的在前面
+    let keys:Vec<String> = lru.snapshot().into_iter().map(|entry| entry.key).collect();
+    assert_eq!(keys, ["3", "1"]);
+
+    lru.reset_stats();
+    assert_eq!(lru.stats().hits, 0);
+    assert_eq!(lru.stats().entries, 2);
+}
+
+#[test]
+fn test_remove() {
+    let file = |content:&str| TFile::new_in_client("test".to_string(), content.as_bytes().to_vec()).unwrap();
+    let mut lru = LRUCache::new(4);
+    for (key, content) in [("1", "a"), ("2", "bb"), ("3", "ccc"), ("4", "dddd")] {
+        lru.put(key.to_string(), file(content));
+    }
+    let _ = lru.get("1".to_string());
+
+    //移除中间的文件后，链表顺序保持不变
+    assert_eq!(lru.invalidate("3").unwrap().read_content(), "ccc".as_bytes().to_vec());
+    assert!(lru.invalidate("3").is_none());
+    assert_eq!(lru.pop_lru().unwrap().0, "2");
+    assert_eq!(lru.bytes(), 5);
+
+    lru.retain(|key, _| key != "4");
+    assert_eq!(lru.pop_lru().unwrap().0, "1");
+    assert!(lru.pop_lru().is_none());
+    assert_eq!(lru.bytes(), 0);
+
+    //清空后可以继续使用
+    lru.put("5".to_string(), file("e"));
+    lru.clear();
+    assert!(!lru.find("5".to_string()) && lru.pop_lru().is_none());
+    lru.put("6".to_string(), file("f"));
+    assert_eq!(lru.pop_lru().unwrap().0, "6");
+}
@@ -0,0 +1,149 @@
+//目录列表
+//列出目录的回复中content是一个个目录项，每一项依次是
+//kind(u8) size(u64) modified(u64) name_len(u32) name
+//大目录分页返回，每一页编码后不超过MAX_LIST_BYTES，加上消息头也能放进一个UDP数据报
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::file::millis;
+use crate::serialize::{ProtocolError, SerialMessage};
+
+//一页目录项编码后的最大字节数
+pub const MAX_LIST_BYTES:usize = 60000;
+//客户端每次请求的目录项数
+pub const LIST_PAGE:u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File = 0,
+    Dir = 1,
+}
+
+impl TryFrom<u8> for EntryKind {
+    type Error = ProtocolError;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(EntryKind::File),
+            1 => Ok(EntryKind::Dir),
+            _ => Err(ProtocolError::UnknownKind(kind)),
+        }
+    }
+}
+
+//目录中的一项，modified是最后修改的时间（毫秒）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name:String,
+    pub kind:EntryKind,
+    pub size:u64,
+    pub modified:u64,
+}
+
+impl DirEntry {
+
+    fn encoded_len(&self) -> usize {
+        1 + 8 + 8 + 4 + self.name.len()
+    }
+
+    fn encode(&self, buf:&mut Vec<u8>) {
+        buf.push(self.kind as u8);
+        buf.extend_from_slice(&self.size.to_be_bytes());
+        buf.extend_from_slice(&self.modified.to_be_bytes());
+        buf.extend_from_slice(&(self.name.len() as u32).to_be_bytes());
+        buf.extend_from_slice(self.name.as_bytes());
+    }
+}
+
+impl fmt::Display for DirEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            EntryKind::File => write!(f, "文件 {} {}字节", self.name, self.size),
+            EntryKind::Dir => write!(f, "目录 {}", self.name),
+        }
+    }
+}
+
+//读取磁盘上的目录，按名字排序，分页时每一页的顺序才是固定的
+//名字不是合法UTF-8的项无法放进消息，直接跳过
+pub fn list_dir<P: AsRef<Path>>(path:P) -> io::Result<Vec<DirEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let metadata = entry.metadata()?;
+        entries.push(DirEntry {
+            name,
+            kind:if metadata.is_dir() { EntryKind::Dir } else { EntryKind::File },
+            size:if metadata.is_dir() { 0 } else { metadata.len() },
+            modified:metadata.modified().map(millis).unwrap_or(0),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+//从entries的开头编码最多number项，超过MAX_LIST_BYTES时提前结束，至少编码一项
+//返回编码的内容和编码了几项
+pub fn encode_page(entries:&[DirEntry], number:usize) -> (Vec<u8>, usize) {
+    let mut buf = Vec::new();
+    let mut count = 0;
+    for entry in entries.iter().take(number.max(1)) {
+        if count > 0 && buf.len() + entry.encoded_len() > MAX_LIST_BYTES {
+            break;
+        }
+        entry.encode(&mut buf);
+        count += 1;
+    }
+    (buf, count)
+}
+
+pub fn decode_entries(content:&[u8]) -> Result<Vec<DirEntry>, ProtocolError> {
+    let mut entries = Vec::new();
+    let mut point = 0;
+    while point < content.len() {
+        let kind = EntryKind::try_from(content[point])?;
+        point += 1;
+        let size = u64::from_be_bytes(SerialMessage::take::<8>(content, point)?);
+        point += 8;
+        let modified = u64::from_be_bytes(SerialMessage::take::<8>(content, point)?);
+        point += 8;
+        let name = SerialMessage::deserialize_bytes(content, &mut point)?;
+        let name = String::from_utf8(name).map_err(|_| ProtocolError::BadUtf8)?;
+        entries.push(DirEntry { name, kind, size, modified });
+    }
+    Ok(entries)
+}
+
+#[test]
+fn test_dir() -> io::Result<()> {
+    let root = "test_dir";
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(format!("{}/sub", root))?;
+    fs::write(format!("{}/a.txt", root), "abc")?;
+
+    let entries = list_dir(root)?;
+    assert_eq!(entries.iter().map(|e| (e.name.as_str(), e.kind, e.size)).collect::<Vec<_>>(),
+        vec![("a.txt", EntryKind::File, 3), ("sub", EntryKind::Dir, 0)]);
+
+    //编码后可以还原，分页时只编码需要的项数
+    let (content, count) = encode_page(&entries, 10);
+    assert_eq!(count, 2);
+    assert_eq!(decode_entries(&content).unwrap(), entries);
+    let (content, count) = encode_page(&entries[1..], 1);
+    assert_eq!((count, decode_entries(&content).unwrap()), (1, entries[1..].to_vec()));
+
+    //超过一个数据报的目录分成多页
+    let many:Vec<DirEntry> = (0..2000).map(|i| DirEntry { name:format!("{:040}", i), ..entries[0].clone() }).collect();
+    let (content, count) = encode_page(&many, 2000);
+    assert!(count < 2000 && content.len() <= MAX_LIST_BYTES);
+
+    assert_eq!(decode_entries(&content[..content.len() - 1]).unwrap_err(), ProtocolError::Truncated);
+    assert_eq!(decode_entries(&[7]).unwrap_err(), ProtocolError::UnknownKind(7));
+    fs::remove_dir_all(root)
+}
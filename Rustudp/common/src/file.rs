@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//把时间转换成从UNIX纪元开始的毫秒数，用于在服务端和客户端之间传递修改时间
+pub fn millis(time:SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[derive(Debug)]
+pub struct TFile {
+    name: String,
+    length: usize,
+    content: Vec<u8>,
+    //服务器上文件最后修改的时间（毫秒），客户端用它判断缓存是否过期
+    modified: u64,
+    //内容被修改但还没有写回磁盘，服务器缓存写回模式下使用
+    dirty: bool,
+}
+
+impl Clone for TFile {
+    fn clone(&self) -> Self {
+        TFile { name:self.name.clone(), 
+                length: self.length, 
+                content: self.content.clone(), 
+                modified: self.modified,
+                dirty: self.dirty,
+        }
+    }
+}
+
+impl TFile {
+    pub fn new(name:String,content: Vec<u8>) -> io::Result<TFile> {
+        let modified = millis(SystemTime::now());
+        let mut file = File::create(name.clone())?;
+        file.write_all(&content)?;
+        Self::stamp(&file, modified)?;
+        Ok(TFile { name,
+                    length: content.len(),
+                    content,
+                    modified,
+                    dirty: false,
+                })
+    }
+
+    pub fn new_in_client(name:String,content: Vec<u8>) -> io::Result<TFile> {
+            Ok(TFile { name,
+                        length: content.len(),
+                        content,
+                        modified: 0,
+                        dirty: false,
+                    })
+        }
+
+    //从磁盘上读取已经存在的文件，用于服务端缓存未命中的情况
+    pub fn open(name:String) -> io::Result<TFile> {
+        let mut file = File::open(&name)?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        let modified = millis(file.metadata()?.modified()?);
+        Ok(TFile { name,
+                    length: content.len(),
+                    content,
+                    modified,
+                    dirty: false,
+                })
+    }
+
+    //在服务器内存中创建文件，还没有写到磁盘上，之后用flush写回
+    pub fn new_unsaved(name:String,content: Vec<u8>) -> TFile {
+        TFile { name,
+                length: content.len(),
+                content,
+                modified: millis(SystemTime::now()),
+                dirty: true,
+        }
+    }
+
+    //从offset开始读取number个字节，number为None时读到文件末尾
+    //number超过剩余长度时只返回剩余部分，offset超出文件长度时返回None
+    pub fn read_bytes(&self, offset:u64, number:Option<u64>) -> Option<Vec<u8>> {
+        let start = usize::try_from(offset).ok().filter(|start| *start <= self.length)?;
+        let end = match number {
+            Some(number) => start.saturating_add(usize::try_from(number).unwrap_or(usize::MAX)).min(self.length),
+            None => self.length,
+        };
+        Some(self.content[start..end].to_vec())
+    }
+
+    pub fn read_tfile(&self) -> io::Result<String> {
+        let mut file = File::open(&self.name)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok(content)
+    }
+
+    //在offset处插入sequence，同时更新TFile的长度和内容，并把整个文件写回磁盘
+    //offset超出文件长度时不做任何修改
+    pub fn write_tfile(&mut self, offset:u64, sequence:Vec<u8>) -> io::Result<()> {
+        self.insert_bytes(offset, sequence)?;
+        self.flush()
+    }
+
+    //在offset处插入sequence，只修改内存中的内容并标记为dirty，不写回磁盘
+    pub fn insert_bytes(&mut self, offset:u64, sequence:Vec<u8>) -> io::Result<()> {
+        let offset = match usize::try_from(offset) {
+            Ok(offset) if offset <= self.length => offset,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "偏移量超出文件范围")),
+        };
+        let mut content = Vec::with_capacity(self.length + sequence.len());
+        content.extend_from_slice(&self.content[..offset]);
+        content.extend(sequence);
+        content.extend_from_slice(&self.content[offset..]);
+
+        self.length = content.len();
+        self.content = content;
+        self.modified = millis(SystemTime::now());
+        self.dirty = true;
+        Ok(())
+    }
+
+    //把还没有写回的内容写到磁盘上
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.dirty {
+            let mut file = File::create(&self.name)?;
+            file.write_all(&self.content)?;
+            Self::stamp(&file, self.modified)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    //磁盘上文件的修改时间设为modified，缓存中和磁盘上的同一个文件回复同样的修改时间
+    //否则写回后磁盘上的时间更晚，文件被淘汰后客户端会把还有效的缓存当成过期
+    fn stamp(file:&File, modified:u64) -> io::Result<()> {
+        file.set_modified(UNIX_EPOCH + Duration::from_millis(modified))
+    }
+
+    //内容是否还没有写回磁盘
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    //读取文件名
+    pub fn read_name(&self) -> String {
+        self.name.clone()
+    }
+
+    //读取最后修改的时间
+    pub fn read_modified(&self) -> u64 {
+        self.modified
+    }
+
+    //客户端缓存的文件记录服务器上的修改时间
+    pub fn set_modified(&mut self, modified:u64) {
+        self.modified = modified;
+    }
+
+    //读取文件长度
+    pub fn read_length(&self) -> usize {
+        self.length
+    }
+
+    //读取缓存中的文件内容
+    pub fn read_content(&self) -> Vec<u8> {
+        self.content.clone()
+    }
+}
+
+#[test]
+fn test_read_bytes() {
+    let t = TFile::new_in_client("test_read".to_string(),"Hello, Rust!".as_bytes().to_vec()).unwrap();
+    assert_eq!(t.read_bytes(0, Some(5)), Some("Hello".as_bytes().to_vec()));
+    assert_eq!(t.read_bytes(7, None), Some("Rust!".as_bytes().to_vec()));
+    //number超过剩余长度时只读到末尾
+    assert_eq!(t.read_bytes(7, Some(100)), Some("Rust!".as_bytes().to_vec()));
+    assert_eq!(t.read_bytes(12, None), Some(Vec::new()));
+    assert_eq!(t.read_bytes(13, Some(1)), None);
+    assert_eq!(t.read_bytes(u64::MAX, Some(1)), None);
+    assert_eq!(t.read_bytes(7, Some(u64::MAX)), Some("Rust!".as_bytes().to_vec()));
+}
+
+#[test]
+fn test() -> io::Result<()>{
+    let my_string = String::from("Hello, Rust!");
+
+    // 将字符串转换为 Vec<u8>
+    let bytes = my_string.into_bytes();
+
+    let t = TFile::new("test1".to_string(),bytes)?;
+    let s = t.read_tfile()?;
+    println!("{:?}",s);
+    Ok(())
+}
+
+#[test]
+fn test_write_tfile() -> io::Result<()> {
+    let mut t = TFile::new("test_write.txt".to_string(),"Hello, Rust!".as_bytes().to_vec())?;
+    t.write_tfile(7, "new ".as_bytes().to_vec())?;
+    assert_eq!(t.read_length(), 16);
+    assert_eq!(t.read_tfile()?, "Hello, new Rust!");
+    assert!(t.write_tfile(17, vec![1]).is_err());
+    assert_eq!(t.read_content(), "Hello, new Rust!".as_bytes().to_vec());
+    std::fs::remove_file("test_write.txt")
+}
+
+#[test]
+fn test_flush() -> io::Result<()> {
+    let name = "test_flush.txt".to_string();
+    let mut t = TFile::new_unsaved(name.clone(), "abcd".as_bytes().to_vec());
+    assert!(t.is_dirty());
+    t.flush()?;
+    assert!(!t.is_dirty());
+
+    //插入只修改内存，flush之后磁盘上才是新的内容
+    t.insert_bytes(2, "XX".as_bytes().to_vec())?;
+    assert_eq!(std::fs::read(&name)?, "abcd".as_bytes().to_vec());
+    std::thread::sleep(Duration::from_millis(20));
+    t.flush()?;
+    assert_eq!(std::fs::read(&name)?, "abXXcd".as_bytes().to_vec());
+
+    //写回后从磁盘读到的修改时间和缓存中的相同
+    assert_eq!(TFile::open(name.clone())?.read_modified(), t.read_modified());
+    std::fs::remove_file(&name)
+}
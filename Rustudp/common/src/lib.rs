@@ -0,0 +1,8 @@
+//客户端和服务端共用的代码
+//消息的编码格式、文件、缓存和传输层只在这里定义一次，双方不会因为各自修改而不兼容
+pub mod cache;
+pub mod dir;
+pub mod file;
+pub mod policy;
+pub mod serialize;
+pub mod transport;
@@ -0,0 +1,377 @@
+//缓存的淘汰策略
+//缓存只负责保存文件，放入、访问和删除时通知策略，满了之后由策略决定移除哪个文件
+//纯LRU在顺序扫描大量文件时会把经常访问的文件全部挤出去，可以换成LFU或者2Q
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+//策略只记录key的顺序，不保存文件内容
+pub trait EvictionPolicy: fmt::Debug {
+    //新的key放入缓存
+    fn on_insert(&mut self, key:&str);
+    //缓存中的key被访问
+    fn on_access(&mut self, key:&str);
+    //key被缓存删除（不是因为淘汰）
+    fn on_remove(&mut self, key:&str);
+    //选出要淘汰的key并且不再记录它，没有key时返回None
+    fn evict(&mut self) -> Option<String>;
+    //忘记所有的key
+    fn clear(&mut self) {
+        while self.evict().is_some() {}
+    }
+}
+
+//构造缓存时选择的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    #[default]
+    Lru,
+    Lfu,
+    TwoQueue,
+    Fifo,
+}
+
+impl Policy {
+    pub fn build(self) -> Box<dyn EvictionPolicy> {
+        match self {
+            Policy::Lru => Box::new(Lru::new()),
+            Policy::Lfu => Box::new(Lfu::new()),
+            Policy::TwoQueue => Box::new(TwoQueue::new()),
+            Policy::Fifo => Box::new(Fifo::new()),
+        }
+    }
+}
+
+//命令行参数policy=lru/lfu/2q/fifo
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s:&str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(Policy::Lru),
+            "lfu" => Ok(Policy::Lfu),
+            "2q" | "twoqueue" => Ok(Policy::TwoQueue),
+            "fifo" => Ok(Policy::Fifo),
+            _ => Err(format!("未知的淘汰策略: {}", s)),
+        }
+    }
+}
+
+
+//LRU由哈希表和链表构成
+//哈希表负责访问为O(1)
+//链表负责访问后的调整顺序和普通的加入操作是O(1)
+//链表头是最久没访问的key，链表尾是最新访问的key
+#[derive(Debug, Default)]
+pub struct Lru {
+    map:HashMap<String,Rc<RefCell<ListNode>>>,
+    first:Option<Rc<RefCell<ListNode>>>,
+    last:Option<Rc<RefCell<ListNode>>>,
+}
+
+#[derive(Debug)]
+struct ListNode {
+    key:String,
+    next:Option<Rc<RefCell<ListNode>>>,
+    prev:Option<Rc<RefCell<ListNode>>>,
+}
+
+impl Lru {
+
+    pub fn new() -> Self {
+        Lru::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn contains(&self, key:&str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    //从链表中间删除
+    fn remove(&mut self,node:&Rc<RefCell<ListNode>>) {
+        match (node.clone().borrow().prev.as_ref(), node.clone().borrow().next.as_ref()) {
+            (Some(pnode),Some(nnode))=> {
+                pnode.borrow_mut().next = node.clone().borrow().next.clone();
+                nnode.borrow_mut().prev = node.clone().borrow().prev.clone();
+            },
+            (Some(pnode),None)=> {
+                pnode.borrow_mut().next = None;
+                self.last = node.clone().borrow().prev.clone();
+            },
+            (None,Some(nnode))=> {
+                nnode.borrow_mut().prev = None;
+                self.first = node.clone().borrow().next.clone();
+            },
+            (None,None)=> {
+                self.first = None;
+                self.last = None;
+            }
+        }
+        let key = node.borrow().key.clone();
+        self.map.remove(&key);
+    }
+
+    //添加到链表的末尾（最新访问区）
+    fn offer_last(&mut self,node:&Rc<RefCell<ListNode>>) {
+        let node = node.clone();
+        node.borrow_mut().next = None;
+        node.borrow_mut().prev = None;
+        if self.first.is_none() {
+            self.first = Some(node.clone());
+            self.last = Some(node.clone());
+        }else {
+            if let Some(ref n) = self.last {
+                node.borrow_mut().prev = Some(n.clone());
+                n.borrow_mut().next = Some(node.clone());
+                self.last = Some(node.clone());
+            }
+        }
+        let key = node.borrow().key.clone();
+        self.map.insert(key,node.clone());
+    }
+
+    //移除链表的第一个元素（最久没访问的key）
+    fn poll_first(&mut self) -> Option<String> {
+        let node = self.first.take()?;
+        let key = node.borrow().key.clone();
+        self.map.remove(&key);
+        match node.borrow().next.as_ref() {
+            Some(next)=>{
+                next.borrow_mut().prev = None;
+                self.first = Some(next.clone());
+            },
+            None=> {
+                self.first = None;
+                self.last = None;
+            },
+        }
+        Some(key)
+    }
+}
+
+impl EvictionPolicy for Lru {
+
+    fn on_insert(&mut self, key:&str) {
+        self.on_remove(key);
+        let node = Rc::new(RefCell::new(ListNode { key:key.to_string(), next:None, prev:None }));
+        self.offer_last(&node);
+    }
+
+    fn on_access(&mut self, key:&str) {
+        if let Some(node) = self.map.get(key) {
+            let node = node.clone();
+            self.remove(&node);
+            self.offer_last(&node);
+        }
+    }
+
+    fn on_remove(&mut self, key:&str) {
+        if let Some(node) = self.map.get(key) {
+            let node = node.clone();
+            self.remove(&node);
+        }
+    }
+
+    fn evict(&mut self) -> Option<String> {
+        self.poll_first()
+    }
+}
+
+
+//LFU：淘汰访问次数最少的key，次数相同时淘汰最早访问的
+//按(次数,访问时刻)排序，淘汰时取最小的
+#[derive(Debug, Default)]
+pub struct Lfu {
+    //key->(访问次数,最近一次访问的时刻)
+    counts:HashMap<String,(u64,u64)>,
+    order:BTreeSet<(u64,u64,String)>,
+    //逻辑时钟，每次放入或访问加一
+    tick:u64,
+}
+
+impl Lfu {
+
+    pub fn new() -> Self {
+        Lfu::default()
+    }
+
+    fn touch(&mut self, key:&str, count:u64) {
+        self.tick += 1;
+        self.counts.insert(key.to_string(), (count, self.tick));
+        self.order.insert((count, self.tick, key.to_string()));
+    }
+}
+
+impl EvictionPolicy for Lfu {
+
+    fn on_insert(&mut self, key:&str) {
+        self.on_remove(key);
+        self.touch(key, 1);
+    }
+
+    fn on_access(&mut self, key:&str) {
+        if let Some((count, tick)) = self.counts.remove(key) {
+            self.order.remove(&(count, tick, key.to_string()));
+            self.touch(key, count + 1);
+        }
+    }
+
+    fn on_remove(&mut self, key:&str) {
+        if let Some((count, tick)) = self.counts.remove(key) {
+            self.order.remove(&(count, tick, key.to_string()));
+        }
+    }
+
+    fn evict(&mut self) -> Option<String> {
+        let (_, _, key) = self.order.pop_first()?;
+        self.counts.remove(&key);
+        Some(key)
+    }
+}
+
+
+//2Q：新放入的key先进入先进先出的a1in队列，只被访问一次的key（比如扫描）从这里被淘汰
+//从a1in淘汰的key记在a1out里（只有key，没有内容），再次放入时说明经常访问，直接进入按LRU管理的am
+//a1in最多占缓存的四分之一，a1out最多记录缓存中key数量的一半
+#[derive(Debug, Default)]
+pub struct TwoQueue {
+    a1in:VecDeque<String>,
+    a1out:VecDeque<String>,
+    ghosts:HashSet<String>,
+    am:Lru,
+}
+
+impl TwoQueue {
+
+    pub fn new() -> Self {
+        TwoQueue::default()
+    }
+
+    fn forget_ghost(&mut self, key:&str) -> bool {
+        if self.ghosts.remove(key) {
+            self.a1out.retain(|k| k != key);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl EvictionPolicy for TwoQueue {
+
+    fn on_insert(&mut self, key:&str) {
+        self.on_remove(key);
+        if self.forget_ghost(key) {
+            self.am.on_insert(key);
+        } else {
+            self.a1in.push_back(key.to_string());
+        }
+    }
+
+    //a1in中的key被访问不改变顺序，避免短时间内的几次访问把它提升到am
+    fn on_access(&mut self, key:&str) {
+        self.am.on_access(key);
+    }
+
+    fn on_remove(&mut self, key:&str) {
+        self.a1in.retain(|k| k != key);
+        self.am.on_remove(key);
+    }
+
+    fn evict(&mut self) -> Option<String> {
+        let resident = self.a1in.len() + self.am.len();
+        if self.a1in.len() * 4 > resident || self.am.is_empty() {
+            let key = self.a1in.pop_front()?;
+            self.ghosts.insert(key.clone());
+            self.a1out.push_back(key.clone());
+            while self.a1out.len() > resident / 2 {
+                if let Some(old) = self.a1out.pop_front() {
+                    self.ghosts.remove(&old);
+                }
+            }
+            Some(key)
+        } else {
+            self.am.evict()
+        }
+    }
+
+    //淘汰会把key记进a1out，清空时连a1out一起清空
+    fn clear(&mut self) {
+        *self = TwoQueue::new();
+    }
+}
+
+
+//FIFO：按放入的顺序淘汰，访问不改变顺序
+#[derive(Debug, Default)]
+pub struct Fifo {
+    queue:VecDeque<String>,
+}
+
+impl Fifo {
+
+    pub fn new() -> Self {
+        Fifo::default()
+    }
+}
+
+impl EvictionPolicy for Fifo {
+
+    fn on_insert(&mut self, key:&str) {
+        self.on_remove(key);
+        self.queue.push_back(key.to_string());
+    }
+
+    fn on_access(&mut self, _key:&str) {}
+
+    fn on_remove(&mut self, key:&str) {
+        self.queue.retain(|k| k != key);
+    }
+
+    fn evict(&mut self) -> Option<String> {
+        self.queue.pop_front()
+    }
+}
+
+
+#[test]
+fn test_policies() {
+    //放入1,2,3，访问1两次、3一次，然后依次淘汰
+    let evictions = |policy:Policy| {
+        let mut policy = policy.build();
+        for key in ["1", "2", "3"] {
+            policy.on_insert(key);
+        }
+        policy.on_access("1");
+        policy.on_access("1");
+        policy.on_access("3");
+        (0..3).map(|_| policy.evict().unwrap()).collect::<Vec<String>>()
+    };
+    assert_eq!(evictions(Policy::Lru), ["2", "1", "3"]);
+    assert_eq!(evictions(Policy::Lfu), ["2", "3", "1"]);
+    assert_eq!(evictions(Policy::Fifo), ["1", "2", "3"]);
+    assert_eq!(evictions(Policy::TwoQueue), ["1", "2", "3"]);
+
+    //2Q：被淘汰过又放回来的key进入am，之后的扫描不会把它挤出去
+    let mut policy = TwoQueue::new();
+    for key in ["hot", "a", "b", "c"] {
+        policy.on_insert(key);
+    }
+    assert_eq!(policy.evict().as_deref(), Some("hot"));
+    policy.on_insert("hot");
+    for key in ["d", "e", "f"] {
+        policy.on_insert(key);
+        assert_ne!(policy.evict().as_deref(), Some("hot"));
+    }
+    assert_eq!("LFU".parse::<Policy>(), Ok(Policy::Lfu));
+    assert!("mru".parse::<Policy>().is_err());
+}
@@ -0,0 +1,549 @@
+// 创建文件 operation,name,content,number是其他客户端的权限(没有时可读可写),sequence是解锁文件的密码
+// 读取文件 operation,name,offset,number,content
+// 非幂等插入 operation,name,offset,sequence
+// 幂等计算长度 operation,name
+// callback operation,name,offset,number
+// 幂等查询最后修改时间 operation,name，回复的modified是服务器上的修改时间
+// 缓存失效 operation,name，服务器在文件被修改后发给缓存了该文件的客户端
+// 幂等查询服务器缓存的统计数据 operation，回复的content是统计数据的文字
+// 非幂等删除文件 operation,name，服务器也会把它发给缓存了该文件的客户端
+// 非幂等重命名 operation,name,content是新的文件名
+// 非幂等创建目录、删除空目录 operation,name
+// 幂等列出目录 operation,name,offset是从第几项开始,number是最多返回几项
+//   回复的number是目录的总项数，content是按dir.rs编码的目录项
+// 解锁文件 operation,name,content是密码,number是申请的权限(没有时可读可写)
+// 登录 operation,name是用户名,content是密码，回复的token是会话令牌,number是令牌的有效期（秒）
+// 退出登录 operation，令牌失效
+// 服务器开启身份认证后，除了登录以外的请求都要带上token
+// operation = 6 时是服务器的回复,status是结果，content是回复的数据或者直接打印的信息
+
+use std::fmt;
+use std::io;
+
+//操作数，编码时是一个字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Create = 1,
+    Read = 2,
+    Insert = 3,
+    Length = 4,
+    Callback = 5,
+    Message = 6,
+    Modified = 7,
+    Invalidate = 8,
+    Stats = 9,
+    Delete = 10,
+    Rename = 11,
+    MakeDir = 12,
+    RemoveDir = 13,
+    ListDir = 14,
+    Unlock = 15,
+    Login = 16,
+    Logout = 17,
+}
+
+impl TryFrom<u8> for Operation {
+    type Error = ProtocolError;
+
+    fn try_from(operation: u8) -> Result<Self, Self::Error> {
+        match operation {
+            1 => Ok(Operation::Create),
+            2 => Ok(Operation::Read),
+            3 => Ok(Operation::Insert),
+            4 => Ok(Operation::Length),
+            5 => Ok(Operation::Callback),
+            6 => Ok(Operation::Message),
+            7 => Ok(Operation::Modified),
+            8 => Ok(Operation::Invalidate),
+            9 => Ok(Operation::Stats),
+            10 => Ok(Operation::Delete),
+            11 => Ok(Operation::Rename),
+            12 => Ok(Operation::MakeDir),
+            13 => Ok(Operation::RemoveDir),
+            14 => Ok(Operation::ListDir),
+            15 => Ok(Operation::Unlock),
+            16 => Ok(Operation::Login),
+            17 => Ok(Operation::Logout),
+            _ => Err(ProtocolError::UnknownOperation(operation)),
+        }
+    }
+}
+
+//文件的权限，创建和解锁文件时number是权限按位或的组合
+pub const PERMISSION_READ:u64 = 1;
+pub const PERMISSION_WRITE:u64 = 2;
+pub const PERMISSION_ALL:u64 = PERMISSION_READ | PERMISSION_WRITE;
+
+//读取文件时一次回复的最大字节数，加上消息头也能放进一个UDP数据报
+pub const MAX_READ_BYTES:u64 = 60000;
+
+//回复的状态码，客户端根据状态码判断结果，不需要比较回复的文字
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    AlreadyExists = 1,
+    NotFound = 2,
+    OutOfRange = 3,
+    PermissionDenied = 4,
+    BadRequest = 5,
+}
+
+impl TryFrom<u8> for Status {
+    type Error = ProtocolError;
+
+    fn try_from(status: u8) -> Result<Self, Self::Error> {
+        match status {
+            0 => Ok(Status::Ok),
+            1 => Ok(Status::AlreadyExists),
+            2 => Ok(Status::NotFound),
+            3 => Ok(Status::OutOfRange),
+            4 => Ok(Status::PermissionDenied),
+            5 => Ok(Status::BadRequest),
+            _ => Err(ProtocolError::UnknownStatus(status)),
+        }
+    }
+}
+
+impl Status {
+    //状态码默认的说明，回复里没有信息时使用
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Status::Ok => "成功",
+            Status::AlreadyExists => "服务器上已经存在该文件",
+            Status::NotFound => "服务器上不存在该文件",
+            Status::OutOfRange => "偏移量超出文件范围",
+            Status::PermissionDenied => "没有权限",
+            Status::BadRequest => "无法解析的请求",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SerialMessage {
+    operation:Operation,
+    name:String,
+    offset:Option<u64>,
+    number:Option<u64>,
+    sequence:Option<Vec<u8>>,
+    content:Option<Vec<u8>>,
+    //请求编号，客户端发出的每条请求单增，重传的请求编号相同
+    //服务器的回复带上对应请求的编号，ip+编号唯一的区分了一条信息
+    id:u32,
+    //回复的状态码，请求中总是Ok
+    status:Status,
+    //文件在服务器上最后修改的时间（毫秒），只在和文件内容相关的回复中存在
+    modified:Option<u64>,
+    //会话令牌，登录后客户端的每条请求都带上
+    token:Option<u128>,
+}
+
+impl Clone for SerialMessage {
+    fn clone(&self) -> Self {
+        SerialMessage {
+            operation: self.operation,
+            name: self.name.clone(),
+            offset:self.offset,
+            number:self.number,
+            sequence:self.sequence.clone(),
+            content:self.content.clone(),
+            id:self.id,
+            status:self.status,
+            modified:self.modified,
+            token:self.token,
+        }
+    }
+}
+
+//这个里面的所有函数都是与读操作有关
+impl SerialMessage {
+
+    //读操作数
+    pub fn read_operation(&self)->Operation {
+        self.operation
+    }
+
+    //读状态码
+    pub fn read_status(&self)->Status {
+        self.status
+    }
+
+    //读服务器上文件最后修改的时间
+    pub fn read_modified(&self)->Option<u64> {
+        self.modified
+    }
+
+    //读会话令牌
+    pub fn read_token(&self)->Option<u128> {
+        self.token
+    }
+
+    //读请求编号
+    pub fn read_id(&self)->u32 {
+        self.id
+    }
+
+    //读name
+    pub fn read_name(&self)->String {
+        self.name.clone()
+    }
+
+    //读offset，offset和number在部分操作中可以不填，所以返回Option
+    pub fn read_offset(&self)->Option<u64> {
+        self.offset
+    }
+
+    //读number
+    pub fn read_number(&self)->Option<u64> {
+        self.number
+    }
+
+    //读sequence
+    pub fn read_sequence(&self)->Option<Vec<u8>> {
+        self.sequence.clone()
+    }
+
+    //读content，没有content时返回空
+    pub fn read_content(&self)->Vec<u8> {
+        self.content.clone().unwrap_or_default()
+    }
+
+    //打印日志用的摘要，只有操作数、文件名、编号和状态码
+    //sequence和content中可能是密码，令牌可以冒充用户，都不打印
+    pub fn summary(&self)->String {
+        format!("{:?} {:?} 编号{} 状态{:?}", self.operation, self.name, self.id, self.status)
+    }
+}
+
+//规则
+//所有整数都是定长的大端编码，变长的字段前面是u32的长度
+//version(u8) operation(u8) status(u8) id(u32) flags(u8) name_len(u32) name
+//[offset(u64)] [number(u64)] [sequence_len(u32) sequence] [content_len(u32) content] [modified(u64)] [token(u128)]
+//flags的第0到5位分别表示offset,number,sequence,content,modified,token是否存在，不存在的字段不写入
+//例子：
+//5,1,0,0,0,0,7,8,0,0,0,7,G,A,O,.,t,x,t,0,0,0,7,C,o,n,t,e,n,t
+//解释
+//版本5，操作数1，状态码0，请求编号7，flags=8只有content，name长度7为GAO.txt，content长度7为Content
+
+//当前的编码版本，格式改变时需要增加
+pub const VERSION:u8 = 5;
+
+const HAS_OFFSET:u8 = 1;
+const HAS_NUMBER:u8 = 1 << 1;
+const HAS_SEQUENCE:u8 = 1 << 2;
+const HAS_CONTENT:u8 = 1 << 3;
+const HAS_MODIFIED:u8 = 1 << 4;
+const HAS_TOKEN:u8 = 1 << 5;
+
+//这个里面的所有函数都跟加密相关
+impl SerialMessage {
+    pub fn new(operation:Operation, name:String, offset:Option<u64>, 
+        number:Option<u64>, sequence:Option<Vec<u8>>, content:Option<Vec<u8>>) -> Self {
+
+        SerialMessage { operation, name, offset, number, sequence, content, id:0, status:Status::Ok, modified:None, token:None}
+    }
+
+    //服务器的回复，content是回复的数据或者信息
+    pub fn reply(status:Status, name:String, content:Option<Vec<u8>>) -> Self {
+        let mut reply = SerialMessage::new(Operation::Message, name, None, None, None, content);
+        reply.status = status;
+        reply
+    }
+
+    //设置文件在服务器上最后修改的时间
+    pub fn set_modified(&mut self, modified:u64) {
+        self.modified = Some(modified);
+    }
+
+    //设置会话令牌
+    pub fn set_token(&mut self, token:u128) {
+        self.token = Some(token);
+    }
+
+    //设置请求编号，客户端发送前设置，服务器回复时设置为对应请求的编号
+    pub fn set_id(&mut self, id:u32) {
+        self.id = id;
+    }
+
+    pub fn serialize_message(&self)->Vec<u8> {
+        let mut serial_vec = Vec::with_capacity(self.serialized_len());
+        serial_vec.push(VERSION);
+        serial_vec.push(self.operation as u8);
+        serial_vec.push(self.status as u8);
+        serial_vec.extend(self.id.to_be_bytes());
+        serial_vec.push(self.serialize_flags());
+        Self::serialize_bytes(&mut serial_vec, self.name.as_bytes());
+        if let Some(offset) = self.offset {
+            serial_vec.extend(offset.to_be_bytes());
+        }
+        if let Some(number) = self.number {
+            serial_vec.extend(number.to_be_bytes());
+        }
+        if let Some(sequence) = &self.sequence {
+            Self::serialize_bytes(&mut serial_vec, sequence);
+        }
+        if let Some(content) = &self.content {
+            Self::serialize_bytes(&mut serial_vec, content);
+        }
+        if let Some(modified) = self.modified {
+            serial_vec.extend(modified.to_be_bytes());
+        }
+        if let Some(token) = self.token {
+            serial_vec.extend(token.to_be_bytes());
+        }
+        serial_vec
+    }
+
+    fn serialize_flags(&self)->u8 {
+        let mut flags = 0;
+        if self.offset.is_some() {
+            flags |= HAS_OFFSET;
+        }
+        if self.number.is_some() {
+            flags |= HAS_NUMBER;
+        }
+        if self.sequence.is_some() {
+            flags |= HAS_SEQUENCE;
+        }
+        if self.content.is_some() {
+            flags |= HAS_CONTENT;
+        }
+        if self.modified.is_some() {
+            flags |= HAS_MODIFIED;
+        }
+        if self.token.is_some() {
+            flags |= HAS_TOKEN;
+        }
+        flags
+    }
+
+    //写入u32的长度和具体的字节
+    fn serialize_bytes(serial_vec:&mut Vec<u8>, bytes:&[u8]) {
+        serial_vec.extend((bytes.len() as u32).to_be_bytes());
+        serial_vec.extend_from_slice(bytes);
+    }
+
+    //编码后的长度，用来预先分配空间
+    fn serialized_len(&self)->usize {
+        12 + self.name.len()
+            + self.offset.map_or(0, |_| 8)
+            + self.number.map_or(0, |_| 8)
+            + self.sequence.as_ref().map_or(0, |sequence| 4 + sequence.len())
+            + self.content.as_ref().map_or(0, |content| 4 + content.len())
+            + self.modified.map_or(0, |_| 8)
+            + self.token.map_or(0, |_| 16)
+    }
+}
+
+//反序列化时的错误
+//收到的数据报可能被截断或者被恶意构造，不能因为一个坏的数据报让整个服务器崩溃
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    Truncated,//数据报比字段声明的长度短
+    UnsupportedVersion(u8),//编码版本不一致
+    UnknownOperation(u8),//不认识的操作数
+    UnknownStatus(u8),//不认识的状态码
+    BadUtf8,//name不是合法的UTF-8
+    UnknownKind(u8),//目录列表中不认识的类型
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Truncated => write!(f, "数据报被截断"),
+            ProtocolError::UnsupportedVersion(version) => write!(f, "不支持的编码版本{}", version),
+            ProtocolError::UnknownOperation(operation) => write!(f, "未知的操作数{}", operation),
+            ProtocolError::UnknownStatus(status) => write!(f, "未知的状态码{}", status),
+            ProtocolError::BadUtf8 => write!(f, "文件名不是合法的UTF-8"),
+            ProtocolError::UnknownKind(kind) => write!(f, "未知的目录项类型{}", kind),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl From<ProtocolError> for io::Error {
+    fn from(e: ProtocolError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+//这个里面与反序列化相关
+impl SerialMessage {
+    pub fn deserialize(serial_vec:Vec<u8>)->Result<Self,ProtocolError> {
+        let version = *serial_vec.first().ok_or(ProtocolError::Truncated)?;
+        if version != VERSION {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+        let operation = Operation::try_from(*serial_vec.get(1).ok_or(ProtocolError::Truncated)?)?;
+        let status = Status::try_from(*serial_vec.get(2).ok_or(ProtocolError::Truncated)?)?;
+        let id = Self::peek_id(&serial_vec).ok_or(ProtocolError::Truncated)?;
+        let flags = *serial_vec.get(7).ok_or(ProtocolError::Truncated)?;
+        let mut point = 8;
+        let name = Self::deserialize_bytes(&serial_vec, &mut point)?;
+        let name = String::from_utf8(name).map_err(|_| ProtocolError::BadUtf8)?;
+
+        let offset = if flags & HAS_OFFSET == 0 {None} 
+        else {Some(Self::deserialize_u64(&serial_vec, &mut point)?)};
+
+        let number = if flags & HAS_NUMBER == 0 {None} 
+        else {Some(Self::deserialize_u64(&serial_vec, &mut point)?)};
+
+        let sequence = if flags & HAS_SEQUENCE == 0 {None} 
+        else {Some(Self::deserialize_bytes(&serial_vec, &mut point)?)};
+
+        let content = if flags & HAS_CONTENT == 0 {None} 
+        else {Some(Self::deserialize_bytes(&serial_vec, &mut point)?)};
+
+        let modified = if flags & HAS_MODIFIED == 0 {None} 
+        else {Some(Self::deserialize_u64(&serial_vec, &mut point)?)};
+
+        let token = if flags & HAS_TOKEN == 0 {None} 
+        else {Some(u128::from_be_bytes(Self::take::<16>(&serial_vec, point)?))};
+
+        Ok(SerialMessage {operation, 
+            name, 
+            offset, number, sequence, content, id, status, modified, token,})
+    }
+
+    //只读出请求编号，用于回复无法解析的请求
+    pub fn peek_id(serial_vec:&[u8])->Option<u32> {
+        Self::take::<4>(serial_vec, 3).ok().map(u32::from_be_bytes)
+    }
+
+    //从point开始读N个字节
+    pub(crate) fn take<const N:usize>(serial_vec:&[u8], point:usize)->Result<[u8;N],ProtocolError> {
+        serial_vec.get(point..point + N)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(ProtocolError::Truncated)
+    }
+
+    //读u64，point移动到下一个字段
+    fn deserialize_u64(serial_vec:&[u8], point:&mut usize)->Result<u64,ProtocolError> {
+        let value = u64::from_be_bytes(Self::take::<8>(serial_vec, *point)?);
+        *point += 8;
+        Ok(value)
+    }
+
+    //读u32的长度和具体的字节，point移动到下一个字段
+    pub(crate) fn deserialize_bytes(serial_vec:&[u8], point:&mut usize)->Result<Vec<u8>,ProtocolError> {
+        let len = u32::from_be_bytes(Self::take::<4>(serial_vec, *point)?) as usize;
+        *point += 4;
+        let bytes = serial_vec.get(*point..point.saturating_add(len))
+            .ok_or(ProtocolError::Truncated)?
+            .to_vec();
+        *point += len;
+        Ok(bytes)
+    }
+}
+
+#[test]
+fn test_serialization() {
+    let mut v1 = Vec::with_capacity(16);
+    v1.push(1);
+    let mut v2 = Vec::with_capacity(16);
+    v2.push(2);
+    v1.extend(v2);
+    //println!("{}",v1.len());
+
+    let ser = SerialMessage::new(Operation::Create,"test.txt".to_string(),
+                    None,Some(8),Some(vec![8,10,45,2]),None);
+    println!("{:?}",ser.serialize_message());
+    let de = SerialMessage::deserialize(ser.serialize_message()).unwrap();
+    println!("{:?}",de);
+}
+
+#[test]
+fn test_serialization_id() {
+    let mut ser = SerialMessage::new(Operation::Insert,"test.txt".to_string(),
+                    Some(2),None,Some(vec![8,10,45,2]),None);
+    ser.set_id(258);
+    let de = SerialMessage::deserialize(ser.serialize_message()).unwrap();
+    assert_eq!(de.read_id(), 258);
+    assert_eq!(de.read_operation(), Operation::Insert);
+    assert_eq!(de.read_name(), "test.txt");
+    assert_eq!(de.read_offset(), Some(2));
+    assert_eq!(de.read_sequence(), Some(vec![8,10,45,2]));
+}
+
+#[test]
+fn test_serialization_large() {
+    //超过255字节的内容和偏移量也能正确的编码和解码
+    let content:Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+    let mut ser = SerialMessage::new(Operation::Read,"a".repeat(300),
+                    Some(70000),Some(300),None,Some(content.clone()));
+    ser.set_id(u32::MAX);
+    let serial_vec = ser.serialize_message();
+    assert_eq!(serial_vec[0], VERSION);
+    assert_eq!(serial_vec.len(), ser.serialized_len());
+    let de = SerialMessage::deserialize(serial_vec).unwrap();
+    assert_eq!(de.read_id(), u32::MAX);
+    assert_eq!(de.read_name(), "a".repeat(300));
+    assert_eq!(de.read_offset(), Some(70000));
+    assert_eq!(de.read_number(), Some(300));
+    assert_eq!(de.read_sequence(), None);
+    assert_eq!(de.read_content(), content);
+
+    //超过4GiB的偏移量和长度也能编码
+    let ser = SerialMessage::new(Operation::Read,"test.txt".to_string(),Some(5 << 32),Some(u64::MAX),None,None);
+    let de = SerialMessage::deserialize(ser.serialize_message()).unwrap();
+    assert_eq!((de.read_offset(), de.read_number()), (Some(5 << 32), Some(u64::MAX)));
+}
+
+#[test]
+fn test_deserialize_error() {
+    let mut ser = SerialMessage::new(Operation::Insert,"test.txt".to_string(),
+                    Some(2),None,Some(vec![8,10,45,2]),None);
+    ser.set_id(9);
+    let serial_vec = ser.serialize_message();
+
+    //任意位置截断都返回错误而不是崩溃
+    for len in 0..serial_vec.len() {
+        assert!(SerialMessage::deserialize(serial_vec[..len].to_vec()).is_err());
+    }
+    assert_eq!(SerialMessage::peek_id(&serial_vec[..7]), Some(9));
+
+    let mut bad = serial_vec.clone();
+    bad[0] = 0;
+    assert_eq!(SerialMessage::deserialize(bad).unwrap_err(), ProtocolError::UnsupportedVersion(0));
+
+    let mut bad = serial_vec.clone();
+    bad[1] = 99;
+    assert_eq!(SerialMessage::deserialize(bad).unwrap_err(), ProtocolError::UnknownOperation(99));
+
+    let mut bad = serial_vec.clone();
+    bad[2] = 99;
+    assert_eq!(SerialMessage::deserialize(bad).unwrap_err(), ProtocolError::UnknownStatus(99));
+
+    //name的长度声明得比实际的长
+    let mut bad = serial_vec.clone();
+    bad[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_eq!(SerialMessage::deserialize(bad).unwrap_err(), ProtocolError::Truncated);
+
+    let mut bad = serial_vec.clone();
+    bad[12] = 0xff;
+    assert_eq!(SerialMessage::deserialize(bad).unwrap_err(), ProtocolError::BadUtf8);
+}
+
+#[test]
+fn test_summary() {
+    let mut login = SerialMessage::new(Operation::Login,"alice".to_string(),None,None,Some(b"seq".to_vec()),Some(b"secret".to_vec()));
+    login.set_id(7);
+    login.set_token(123456789);
+    let summary = login.summary();
+    assert_eq!(summary, "Login \"alice\" 编号7 状态Ok");
+    assert!(!summary.contains("secret") && !summary.contains("123456789"));
+}
+
+#[test]
+fn test_reply_status() {
+    let mut reply = SerialMessage::reply(Status::NotFound, "test.txt".to_string(), None);
+    reply.set_id(3);
+    reply.set_modified(1_700_000_000_123);
+    reply.set_token(u128::MAX - 1);
+    let de = SerialMessage::deserialize(reply.serialize_message()).unwrap();
+    assert_eq!(de.read_modified(), Some(1_700_000_000_123));
+    assert_eq!(de.read_token(), Some(u128::MAX - 1));
+    assert_eq!(de.read_operation(), Operation::Message);
+    assert_eq!(de.read_status(), Status::NotFound);
+    assert_eq!(de.read_id(), 3);
+}
@@ -0,0 +1,204 @@
+//模拟不可靠网络的传输层，包装UdpSocket
+//本机上UDP几乎不会丢包，为了测试调用语义和重传，需要按概率丢弃、延迟、重复和乱序数据报
+//发送和接收分别设置概率，同一个种子得到相同的丢包序列，便于复现问题
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+//一个方向上的故障概率，全部为0时就是可靠的网络
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    pub drop: f64,//丢弃的概率
+    pub duplicate: f64,//重复发送或接收的概率
+    pub reorder: f64,//和下一个数据报交换顺序的概率
+    pub delay: f64,//延迟的概率
+    pub delay_time: Duration,//延迟的时间
+}
+
+impl Faults {
+    //只丢包的网络
+    pub fn lossy(drop: f64) -> Self {
+        Faults { drop, ..Faults::default() }
+    }
+}
+
+//xorshift64*伪随机数，不需要引入额外的依赖
+#[derive(Debug)]
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    //返回[0,1)之间的随机数
+    fn next_f64(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let value = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn hit(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
+
+#[derive(Debug)]
+pub struct Transport {
+    socket: UdpSocket,
+    outgoing: Faults,
+    incoming: Faults,
+    rng: Rng,
+    //被推迟发送的数据报，下一次发送之后再发出去
+    held: Option<(Vec<u8>, SocketAddr)>,
+    //已经收到但还没交给上层的数据报（重复或乱序产生的）
+    pending: VecDeque<(Vec<u8>, SocketAddr)>,
+}
+
+impl Transport {
+
+    //可靠的传输，直接使用UdpSocket
+    pub fn new(socket: UdpSocket) -> Self {
+        Transport::with_faults(socket, Faults::default(), Faults::default(), 0)
+    }
+
+    pub fn with_faults(socket: UdpSocket, outgoing: Faults, incoming: Faults, seed: u64) -> Self {
+        Transport {
+            socket,
+            outgoing,
+            incoming,
+            rng: Rng::new(seed),
+            held: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    //修改故障概率，同时重置随机数种子
+    pub fn set_faults(&mut self, outgoing: Faults, incoming: Faults, seed: u64) {
+        self.outgoing = outgoing;
+        self.incoming = incoming;
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn connect<A: ToSocketAddrs>(&self, address: A) -> io::Result<()> {
+        self.socket.connect(address)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    //发送数据报，丢弃时也返回成功，和真实网络一样发送方感知不到
+    pub fn send_to<A: ToSocketAddrs>(&mut self, buf: &[u8], address: A) -> io::Result<usize> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "没有可用的地址")
+        })?;
+        if self.rng.hit(self.outgoing.drop) {
+            return Ok(buf.len());
+        }
+        if self.rng.hit(self.outgoing.delay) {
+            thread::sleep(self.outgoing.delay_time);
+        }
+        if self.held.is_none() && self.rng.hit(self.outgoing.reorder) {
+            self.held = Some((buf.to_vec(), address));
+            return Ok(buf.len());
+        }
+        self.socket.send_to(buf, address)?;
+        if self.rng.hit(self.outgoing.duplicate) {
+            self.socket.send_to(buf, address)?;
+        }
+        if let Some((held, held_address)) = self.held.take() {
+            self.socket.send_to(&held, held_address)?;
+        }
+        Ok(buf.len())
+    }
+
+    //接收数据报，被丢弃的数据报直接跳过，继续等待下一个
+    pub fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        if let Some((data, address)) = self.pending.pop_front() {
+            return Ok((Self::copy_to(buf, &data), address));
+        }
+        loop {
+            let (amt, address) = self.socket.recv_from(buf)?;
+            if self.rng.hit(self.incoming.drop) {
+                continue;
+            }
+            if self.rng.hit(self.incoming.delay) {
+                thread::sleep(self.incoming.delay_time);
+            }
+            if self.rng.hit(self.incoming.duplicate) {
+                self.pending.push_back((buf[..amt].to_vec(), address));
+            }
+            //乱序：先把这个数据报放一边，把下一个数据报先交给上层
+            //如果等不到下一个数据报，就直接交出这一个
+            if self.rng.hit(self.incoming.reorder) {
+                let first = buf[..amt].to_vec();
+                return match self.socket.recv_from(buf) {
+                    Ok((next_amt, next_address)) => {
+                        self.pending.push_back((first, address));
+                        Ok((next_amt, next_address))
+                    },
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                        Ok((Self::copy_to(buf, &first), address))
+                    },
+                    Err(e) => Err(e),
+                };
+            }
+            return Ok((amt, address));
+        }
+    }
+
+    fn copy_to(buf: &mut [u8], data: &[u8]) -> usize {
+        let amt = data.len().min(buf.len());
+        buf[..amt].copy_from_slice(&data[..amt]);
+        amt
+    }
+}
+
+#[test]
+fn test_transport() -> io::Result<()> {
+    let receiver = UdpSocket::bind("127.0.0.1:0")?;
+    receiver.set_read_timeout(Some(Duration::from_millis(200)))?;
+    let address = receiver.local_addr()?;
+    let mut receiver = Transport::new(receiver);
+
+    //全部丢弃
+    let mut sender = Transport::with_faults(UdpSocket::bind("127.0.0.1:0")?, Faults::lossy(1.0), Faults::default(), 7);
+    sender.send_to(&[1], address)?;
+    let mut buf = [0u8; 16];
+    assert!(receiver.recv_from(&mut buf).is_err());
+
+    //全部重复
+    let duplicate = Faults { duplicate: 1.0, ..Faults::default() };
+    sender.set_faults(duplicate, Faults::default(), 7);
+    sender.send_to(&[2], address)?;
+    assert_eq!(receiver.recv_from(&mut buf)?.0, 1);
+    assert_eq!(buf[0], 2);
+    assert_eq!(receiver.recv_from(&mut buf)?.0, 1);
+    assert_eq!(buf[0], 2);
+
+    //乱序：第一个数据报在第二个之后到达
+    let reorder = Faults { reorder: 1.0, ..Faults::default() };
+    sender.set_faults(reorder, Faults::default(), 7);
+    sender.send_to(&[3], address)?;
+    sender.send_to(&[4], address)?;
+    receiver.recv_from(&mut buf)?;
+    assert_eq!(buf[0], 4);
+    receiver.recv_from(&mut buf)?;
+    assert_eq!(buf[0], 3);
+    Ok(())
+}
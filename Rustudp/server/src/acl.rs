@@ -0,0 +1,256 @@
+//文件的访问控制
+//创建文件的客户端是文件的所有者，拥有全部权限
+//其他客户端的权限先看访问控制列表，再加上创建时给所有人的权限
+//设置了密码的文件，其他客户端输入正确的密码后加入访问控制列表
+//访问控制保存到文件中，服务器重启后仍然有效
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use common::serialize::PERMISSION_ALL;
+use sha2::{Digest, Sha256};
+
+//哈希的轮数，增加暴力猜测密码的代价
+const ROUNDS:u32 = 10000;
+
+//加盐哈希后的密码，不保存密码本身
+pub struct Cipher {
+    salt:[u8; 16],
+    hash:[u8; 32],
+}
+
+impl Cipher {
+
+    //用随机的盐计算密码的哈希
+    pub fn new(password:&[u8]) -> io::Result<Cipher> {
+        let mut salt = [0u8; 16];
+        getrandom::getrandom(&mut salt).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Cipher { salt, hash:Self::digest(&salt, password) })
+    }
+
+    //比较时不提前结束，避免从比较的时间推测哈希
+    pub fn verify(&self, password:&[u8]) -> bool {
+        let hash = Self::digest(&self.salt, password);
+        hash.iter().zip(self.hash.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+
+    fn digest(salt:&[u8], password:&[u8]) -> [u8; 32] {
+        let mut hash:[u8; 32] = Sha256::new().chain_update(salt).chain_update(password).finalize().into();
+        for _ in 1..ROUNDS {
+            hash = Sha256::new().chain_update(salt).chain_update(hash).finalize().into();
+        }
+        hash
+    }
+}
+
+//保存时是十六进制的 盐:哈希
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |bytes:&[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        write!(f, "{}:{}", hex(&self.salt), hex(&self.hash))
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = String;
+
+    fn from_str(s:&str) -> Result<Self, Self::Err> {
+        fn unhex<const N:usize>(hex:&str) -> Option<[u8; N]> {
+            if hex.len() != N * 2 || !hex.is_ascii() {
+                return None;
+            }
+            let mut bytes = [0u8; N];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+            }
+            Some(bytes)
+        }
+        let (salt, hash) = s.split_once(':').ok_or(format!("无法解析的密码哈希: {}", s))?;
+        match (unhex(salt), unhex(hash)) {
+            (Some(salt), Some(hash)) => Ok(Cipher { salt, hash }),
+            _ => Err(format!("无法解析的密码哈希: {}", s)),
+        }
+    }
+}
+
+pub struct Acl {
+    owner:String,
+    //所有客户端都有的权限
+    others:u64,
+    //客户端->单独给的权限
+    access:HashMap<String,u64>,
+    //没有密码的文件不能解锁
+    cipher:Option<Cipher>,
+}
+
+impl Acl {
+    pub fn new(owner:String, others:u64, cipher:Option<Cipher>) -> Acl {
+        Acl { owner, others:others & PERMISSION_ALL, access:HashMap::new(), cipher }
+    }
+
+    //client是否有permission中的全部权限
+    pub fn allows(&self, client:&str, permission:u64) -> bool {
+        let granted = if client == self.owner {
+            PERMISSION_ALL
+        } else {
+            self.others | self.access.get(client).copied().unwrap_or(0)
+        };
+        granted & permission == permission
+    }
+
+    //用密码解锁文件，密码正确时把permission加入client的权限
+    pub fn unlock(&mut self, client:&str, password:&[u8], permission:u64) -> bool {
+        match &self.cipher {
+            Some(cipher) if cipher.verify(password) => {
+                *self.access.entry(client.to_string()).or_default() |= permission & PERMISSION_ALL;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+//保存时字段之间用制表符分隔：所有者 其他客户端的权限 盐:哈希(没有密码时是-) 然后是每个客户端和它的权限
+//客户端是用户名或者地址，都没有空白
+impl fmt::Display for Acl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t", self.owner, self.others)?;
+        match &self.cipher {
+            Some(cipher) => write!(f, "{}", cipher)?,
+            None => write!(f, "-")?,
+        }
+        let mut clients:Vec<&String> = self.access.keys().collect();
+        clients.sort();
+        for client in clients {
+            write!(f, "\t{}\t{}", client, self.access[client])?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Acl {
+    type Err = String;
+
+    fn from_str(s:&str) -> Result<Self, Self::Err> {
+        let error = || format!("无法解析的访问控制: {}", s);
+        let fields:Vec<&str> = s.split('\t').collect();
+        if fields.len() < 3 || fields.len().is_multiple_of(2) {
+            return Err(error());
+        }
+        let others = fields[1].parse::<u64>().map_err(|_| error())?;
+        let cipher = match fields[2] {
+            "-" => None,
+            cipher => Some(cipher.parse::<Cipher>()?),
+        };
+        let mut acl = Acl::new(fields[0].to_string(), others, cipher);
+        for pair in fields[3..].chunks(2) {
+            let permission = pair[1].parse::<u64>().map_err(|_| error())?;
+            acl.access.insert(pair[0].to_string(), permission & PERMISSION_ALL);
+        }
+        Ok(acl)
+    }
+}
+
+//文件名中的\、制表符和换行需要转义，否则无法按行和制表符解析
+fn escape(name:&str) -> String {
+    name.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(name:&str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+//从文件读取访问控制，文件中是根目录下的相对路径，读出来后拼上root；文件不存在时没有访问控制
+pub fn load<P: AsRef<Path>>(path:P, root:&Path) -> io::Result<HashMap<String,Acl>> {
+    let mut acls = HashMap::new();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(acls),
+        Err(e) => return Err(e),
+    };
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let acl = line.split_once('\t')
+            .and_then(|(name, acl)| Some((name, acl.parse::<Acl>().ok()?)));
+        match acl {
+            Some((name, acl)) => { acls.insert(root.join(unescape(name)).to_string_lossy().into_owned(), acl); },
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无法解析的访问控制: {}", line))),
+        }
+    }
+    Ok(acls)
+}
+
+//每行一个文件：根目录下的相对路径 访问控制；不在root下的路径不保存
+pub fn save<P: AsRef<Path>>(path:P, root:&Path, acls:&HashMap<String,Acl>) -> io::Result<()> {
+    let mut lines:Vec<String> = acls.iter()
+        .filter_map(|(name, acl)| {
+            let name = Path::new(name).strip_prefix(root).ok()?.to_str()?;
+            Some(format!("{}\t{}\n", escape(name), acl))
+        })
+        .collect();
+    lines.sort();
+    fs::write(path, lines.concat())
+}
+
+#[test]
+fn test_acl() -> io::Result<()> {
+    use common::serialize::{PERMISSION_READ, PERMISSION_WRITE};
+    let mut acl = Acl::new("owner".to_string(), PERMISSION_READ, Some(Cipher::new(b"secret")?));
+    assert!(acl.allows("owner", PERMISSION_ALL));
+    assert!(acl.allows("other", PERMISSION_READ));
+    assert!(!acl.allows("other", PERMISSION_WRITE));
+
+    //密码错误时不改变权限，正确时只加入申请的权限
+    assert!(!acl.unlock("other", b"guess", PERMISSION_WRITE));
+    assert!(!acl.allows("other", PERMISSION_ALL));
+    assert!(acl.unlock("other", b"secret", PERMISSION_WRITE));
+    assert!(acl.allows("other", PERMISSION_ALL));
+    assert!(!acl.allows("third", PERMISSION_WRITE));
+
+    //同样的密码每次的盐不同，哈希也不同
+    let (a, b) = (Cipher::new(b"secret")?, Cipher::new(b"secret")?);
+    assert!(a.salt != b.salt && a.hash != b.hash);
+    let c:Cipher = a.to_string().parse().unwrap();
+    assert!(c.verify(b"secret") && !c.verify(b"guess"));
+    assert!("00:11".parse::<Cipher>().is_err());
+
+    //没有密码的文件不能解锁
+    let mut acl = Acl::new("owner".to_string(), 0, None);
+    assert!(!acl.unlock("other", b"", PERMISSION_READ));
+
+    //保存到文件后读回来，权限和密码都不变
+    let (path, root) = ("test_acls.txt", Path::new("/srv/root"));
+    let mut unlocked = Acl::new("owner".to_string(), PERMISSION_READ, Some(Cipher::new(b"secret")?));
+    assert!(unlocked.unlock("127.0.0.1:2", b"secret", PERMISSION_WRITE));
+    let mut acls = HashMap::new();
+    acls.insert("/srv/root/sub/a.txt".to_string(), unlocked);
+    acls.insert("/srv/root/tab\tname\\.txt".to_string(), acl);
+    acls.insert("/elsewhere/b.txt".to_string(), Acl::new("owner".to_string(), 0, None));
+    save(path, root, &acls)?;
+    let loaded = load(path, root)?;
+    fs::remove_file(path)?;
+    assert_eq!(loaded.len(), 2);
+    let acl = &loaded["/srv/root/sub/a.txt"];
+    assert!(acl.allows("owner", PERMISSION_ALL) && acl.allows("127.0.0.1:2", PERMISSION_ALL));
+    assert!(acl.allows("third", PERMISSION_READ) && !acl.allows("third", PERMISSION_WRITE));
+    assert!(acl.cipher.as_ref().unwrap().verify(b"secret"));
+    assert!(!loaded["/srv/root/tab\tname\\.txt"].allows("other", PERMISSION_READ));
+    assert!(load(path, root)?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//客户端的身份认证
+//服务器只保存用户名和加盐哈希后的密码，登录成功后给客户端一个随机的会话令牌
+//之后客户端的请求都带上令牌，令牌只能在登录时的地址上使用，过期或者退出登录后失效
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::acl::Cipher;
+
+//用户不存在时用来验证密码的哈希，任何密码都不会和它匹配
+const DUMMY_CIPHER:&str = "00000000000000000000000000000000:0000000000000000000000000000000000000000000000000000000000000000";
+static DUMMY:OnceLock<Cipher> = OnceLock::new();
+
+//用户名->密码的哈希
+//保存到文件时每行一个用户：用户名 盐:哈希
+#[derive(Default)]
+pub struct Credentials {
+    users:HashMap<String,Cipher>,
+}
+
+impl Credentials {
+    pub fn new() -> Self {
+        Credentials::default()
+    }
+
+    //从文件读取用户，文件不存在时没有用户
+    pub fn load<P: AsRef<Path>>(path:P) -> io::Result<Self> {
+        let mut credentials = Credentials::new();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(credentials),
+            Err(e) => return Err(e),
+        };
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let cipher = line.split_once(' ')
+                .and_then(|(name, cipher)| Some((name, cipher.parse::<Cipher>().ok()?)));
+            match cipher {
+                Some((name, cipher)) => { credentials.users.insert(name.to_string(), cipher); },
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无法解析的用户: {}", line))),
+            }
+        }
+        Ok(credentials)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path:P) -> io::Result<()> {
+        let mut names:Vec<&String> = self.users.keys().collect();
+        names.sort();
+        let text:String = names.into_iter().map(|name| format!("{} {}\n", name, self.users[name])).collect();
+        fs::write(path, text)
+    }
+
+    //添加用户，已经存在的用户修改密码；用户名不能为空也不能有空白
+    pub fn add_user(&mut self, name:&str, password:&[u8]) -> io::Result<()> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("不合法的用户名: {}", name)));
+        }
+        self.users.insert(name.to_string(), Cipher::new(password)?);
+        Ok(())
+    }
+
+    //用户不存在时同样计算一次哈希再返回false，不能从回复的时间推测用户是否存在
+    pub fn verify(&self, name:&str, password:&[u8]) -> bool {
+        let verified = self.cipher(name).verify(password);
+        verified && self.users.contains_key(name)
+    }
+
+    //用户的密码哈希，用户不存在时是固定的哈希
+    fn cipher(&self, name:&str) -> &Cipher {
+        self.users.get(name).unwrap_or_else(|| DUMMY.get_or_init(|| DUMMY_CIPHER.parse().expect("固定的哈希可以解析")))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+struct Session {
+    user:String,
+    address:String,
+    expires:Instant,
+}
+
+//已经登录的会话，令牌->会话
+pub struct Sessions {
+    ttl:Duration,
+    sessions:HashMap<u128,Session>,
+}
+
+impl Sessions {
+    pub fn new(ttl:Duration) -> Self {
+        Sessions { ttl, sessions:HashMap::new() }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn set_ttl(&mut self, ttl:Duration) {
+        self.ttl = ttl;
+    }
+
+    //用户在address上登录，返回新的令牌，顺便清理已经过期的会话
+    pub fn login(&mut self, user:&str, address:&str) -> io::Result<u128> {
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires > now);
+        let mut token = [0u8; 16];
+        getrandom::getrandom(&mut token).map_err(|e| io::Error::other(e.to_string()))?;
+        let token = u128::from_be_bytes(token);
+        self.sessions.insert(token, Session { user:user.to_string(), address:address.to_string(), expires:now + self.ttl });
+        Ok(token)
+    }
+
+    //令牌对应的用户，令牌不存在、已经过期或者从别的地址发来时返回None
+    pub fn user(&mut self, token:u128, address:&str) -> Option<String> {
+        let session = self.sessions.get(&token)?;
+        if session.expires <= Instant::now() {
+            self.sessions.remove(&token);
+            return None;
+        }
+        (session.address == address).then(|| session.user.clone())
+    }
+
+    //退出登录，令牌不存在或者从别的地址发来时返回false
+    pub fn logout(&mut self, token:u128, address:&str) -> bool {
+        if self.user(token, address).is_none() {
+            return false;
+        }
+        self.sessions.remove(&token).is_some()
+    }
+}
+
+#[test]
+fn test_credentials() -> io::Result<()> {
+    let path = "test_users.txt";
+    let mut credentials = Credentials::new();
+    credentials.add_user("alice", b"secret")?;
+    assert!(credentials.add_user("bad name", b"secret").is_err());
+    credentials.save(path)?;
+
+    //文件中只有哈希，读回来后仍然能验证密码
+    let text = fs::read_to_string(path)?;
+    assert!(text.starts_with("alice ") && !text.contains("secret"));
+    let credentials = Credentials::load(path)?;
+    assert!(credentials.verify("alice", b"secret"));
+    assert!(!credentials.verify("alice", b"guess"));
+    assert!(!credentials.verify("bob", b"secret"));
+
+    //不存在的用户用固定的哈希验证，和密码错误一样要计算完整的哈希
+    let dummy = credentials.cipher("bob");
+    assert!(std::ptr::eq(dummy, DUMMY.get().unwrap()));
+    assert!(!std::ptr::eq(credentials.cipher("alice"), dummy));
+    assert!(!dummy.verify(b"") && !dummy.verify(b"secret"));
+    fs::remove_file(path)?;
+    assert!(Credentials::load(path)?.is_empty());
+
+    let mut sessions = Sessions::new(Duration::from_secs(60));
+    let token = sessions.login("alice", "127.0.0.1:1")?;
+    assert_eq!(sessions.user(token, "127.0.0.1:1"), Some("alice".to_string()));
+    assert_eq!(sessions.user(token, "127.0.0.1:2"), None);
+    assert!(!sessions.logout(token, "127.0.0.1:2"));
+    assert!(sessions.logout(token, "127.0.0.1:1"));
+    assert_eq!(sessions.user(token, "127.0.0.1:1"), None);
+
+    //过期的令牌不能使用
+    sessions.set_ttl(Duration::ZERO);
+    let token = sessions.login("alice", "127.0.0.1:1")?;
+    assert_eq!(sessions.user(token, "127.0.0.1:1"), None);
+    Ok(())
+}
@@ -1,162 +1,1490 @@
-pub mod cache;
-pub mod file;
-pub mod serialize;
+mod acl;
+mod auth;
 
-use std::{net::UdpSocket, collections:: HashSet, fs};
+use std::{net::UdpSocket, collections::{HashMap, HashSet, VecDeque}, fs};
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Component, Path, PathBuf};
 //use std::str;
-use serialize::SerialMessage;
-use crate::cache::LRUCache;
+use common::serialize::{Operation, SerialMessage, Status, MAX_READ_BYTES, PERMISSION_ALL, PERMISSION_READ, PERMISSION_WRITE};
+use common::cache::LRUCache;
+use common::dir::{self, DirEntry, EntryKind, LIST_PAGE};
+use common::policy::Policy;
 use std::io;
-use file::TFile;
+use common::file::{self, TFile};
+use common::transport::{Faults, Transport};
+use acl::{Acl, Cipher};
+use auth::{Credentials, Sessions};
+
+//调用语义
+//至少一次：收到的请求都会执行，重传的非幂等请求会被重复执行
+//至多一次：记录非幂等请求的回复，重传的请求直接重发记录的回复，不再执行
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Semantics {
+    AtLeastOnce,
+    AtMostOnce,
+}
+
+//至多一次语义下每个客户端最多记录的回复数，超过后丢弃最早的回复
+const HISTORY_PER_CLIENT:usize = 64;
+
+//会话令牌默认的有效期
+const SESSION_TTL:Duration = Duration::from_secs(30 * 60);
+
+//等待请求时最多等这么久就检查一次是否要关闭服务器
+const SHUTDOWN_POLL:Duration = Duration::from_millis(200);
+
+//服务器缓存的写策略
+//写穿：修改立即写回磁盘
+//写回：修改只保存在服务器缓存里，文件被淘汰、每隔一段时间或者服务器关闭时才写回磁盘
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WritePolicy {
+    WriteThrough,
+    WriteBack(Duration),
+}
 
-#[warn(dead_code)]
 struct Server {
     address:String,//服务端绑定的地址
+    //导出的根目录，客户端只能访问这个目录下的文件
+    //保存规范化后的绝对路径，服务器缓存和磁盘上的文件都用根目录下的完整路径
+    root:PathBuf,
     server_cache:LRUCache,//记录缓存中的文件
-    //file_name_in_memory:HashMap<String,Vec<String>>,//记录内存中文件和文件的权限的拥有者
-    //如果不在内存中，需要在磁盘上查找文件，此时需要权限
-    //不在Vec中则没有权限，需要输入密码来获得权限
-    //此功能还没有开发完全，所以先注释掉了
+    //记录文件的访问控制，根目录下的路径->所有者和各个客户端的权限
+    //不随服务器缓存淘汰；没有记录的文件（不是通过服务器创建的）所有客户端都能访问
+    acls:HashMap<String,Acl>,
+    //保存访问控制的文件，每次修改后写入，服务器重启时读回
+    acl_file:Option<PathBuf>,
+    //服务器自己使用的文件（访问控制、用户和密码的哈希），放在根目录下时客户端也不能访问
+    hidden:Vec<PathBuf>,
+    //用户名和密码的哈希，有用户时开启身份认证
+    credentials:Credentials,
+    //已经登录的会话
+    sessions:Sessions,
     client_address:HashSet<String>,
-    socket:UdpSocket,
-    //可以新加一个变量维护(信息+ip+客户端单增的编号)
+    //记录缓存了文件的客户端，根目录下的路径->客户端地址->客户端使用的文件名
+    //文件被修改后用客户端自己的文件名通知这些客户端缓存失效
+    readers:HashMap<String,HashMap<String,String>>,
+    //记录监控文件的客户端，根目录下的路径->(客户端地址,客户端使用的文件名,监控截止时间)
+    //文件被修改后推送给还在监控时间内的客户端
+    monitors:HashMap<String,Vec<(String,String,Instant)>>,
+    socket:Transport,
+    semantics:Semantics,
+    write_policy:WritePolicy,
+    //上一次把缓存写回磁盘的时间
+    last_flush:Instant,
+    //设置后run返回，服务器被释放时把缓存写回磁盘
+    shutdown:Arc<AtomicBool>,
+    //维护ip->(客户端单增的编号,回复)，按执行的先后排列
     //对于幂等操作不需要操作，重复发送无所谓
-    //对于非幂等的操作只能发送一次，所以根据操作数operation，需要找出需要过滤的操作
-    //用ip+客户端单增的编号进行过滤
+    //对于非幂等的操作只能执行一次，所以根据操作数operation，找出需要过滤的操作
+    //用ip+客户端单增的编号进行过滤，重传只会发生在最近的请求上，每个客户端只保留最近的HISTORY_PER_CLIENT条
+    history:HashMap<String,VecDeque<(u32,SerialMessage)>>,
 }
 
 impl Server {
 
     //创建服务器
-    pub fn new(address:String,capacity:i32)->Self {
+    pub fn new(address:String,capacity:i32,semantics:Semantics)->Self {
+        Server::with_policy(address, capacity, semantics, Policy::Lru)
+    }
+
+    //创建服务器，服务器缓存使用指定的淘汰策略
+    pub fn with_policy(address:String,capacity:i32,semantics:Semantics,policy:Policy)->Self {
         Server {
             address:address.clone(),
-            server_cache: LRUCache::new(capacity),
+            root:fs::canonicalize(".").unwrap(),
+            server_cache: LRUCache::with_policy(capacity, policy),
+            acls:HashMap::new(),
+            acl_file:None,
+            hidden:Vec::new(),
+            credentials:Credentials::new(),
+            sessions:Sessions::new(SESSION_TTL),
             client_address:HashSet::new(),
-            socket:UdpSocket::bind(&address).unwrap(),
+            readers:HashMap::new(),
+            monitors:HashMap::new(),
+            socket:Transport::new(UdpSocket::bind(&address).unwrap()),
+            semantics,
+            history:HashMap::new(),
+            write_policy:WritePolicy::WriteThrough,
+            last_flush:Instant::now(),
+            shutdown:Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    //设置导出的根目录，目录必须已经存在
+    //路径不是合法UTF-8时无法和客户端的文件名拼接，同样拒绝
+    pub fn set_root<P: AsRef<Path>>(&mut self, root:P) -> io::Result<()> {
+        let root = fs::canonicalize(root)?;
+        if !root.is_dir() || root.to_str().is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "根目录不是合法的目录"));
+        }
+        self.root = root;
+        Ok(())
+    }
+
+    //设置保存访问控制的文件，读取文件中已有的访问控制，文件不存在时创建
+    //文件中是根目录下的相对路径，需要先设置根目录
+    pub fn set_acl_file<P: AsRef<Path>>(&mut self, path:P) -> io::Result<()> {
+        self.acls = acl::load(&path, &self.root)?;
+        acl::save(&path, &self.root, &self.acls)?;
+        self.hide_file(&path)?;
+        self.acl_file = Some(fs::canonicalize(path)?);
+        Ok(())
+    }
+
+    //客户端不能读取、修改、删除、重命名或者列出这个文件，文件必须已经存在
+    pub fn hide_file<P: AsRef<Path>>(&mut self, path:P) -> io::Result<()> {
+        self.hidden.push(fs::canonicalize(path)?);
+        Ok(())
+    }
+
+    //访问控制修改后写入文件，失败时只打印错误，内存中的访问控制仍然有效
+    fn save_acls(&self) {
+        if let Some(path) = &self.acl_file {
+            if let Err(e) = acl::save(path, &self.root, &self.acls) {
+                println!("保存访问控制失败:{}",e);
+            }
+        }
+    }
+
+    //设置用户，有用户时除了登录以外的请求都要带上有效的令牌
+    pub fn set_credentials(&mut self, credentials:Credentials) {
+        self.credentials = credentials;
+    }
+
+    //设置会话令牌的有效期，只影响之后的登录
+    pub fn set_session_ttl(&mut self, ttl:Duration) {
+        self.sessions.set_ttl(ttl);
+    }
+
+    //确认请求来自哪个客户端，返回访问控制中使用的身份
+    //开启身份认证时身份是令牌对应的用户名，没有令牌、令牌无效或者从别的地址发来都拒绝
+    //没有开启时身份就是客户端的地址
+    fn authenticate(&mut self, serial_message:&SerialMessage, address:&str) -> Result<String, Status> {
+        if self.credentials.is_empty() {
+            return Ok(address.to_string());
+        }
+        serial_message.read_token()
+            .and_then(|token| self.sessions.user(token, address))
+            .ok_or(Status::PermissionDenied)
+    }
+
+    //登录的操作，name是用户名，content是密码
+    //用户名不存在和密码错误回复同样的结果，不透露用户是否存在
+    fn login(&mut self, serial_message:&SerialMessage, address:&str) -> SerialMessage {
+        if self.credentials.is_empty() {
+            return Self::message_reply(Status::BadRequest, "服务器没有开启身份认证");
+        }
+        let user = serial_message.read_name();
+        if !self.credentials.verify(&user, &serial_message.read_content()) {
+            return Self::status_reply(Status::PermissionDenied);
         }
+        let token = match self.sessions.login(&user, address) {
+            Ok(token) => token,
+            Err(e) => return Self::message_reply(Status::BadRequest, &format!("登录失败{}",e)),
+        };
+        let mut reply = SerialMessage::new(Operation::Message,
+            user,
+            None,
+            Some(self.sessions.ttl().as_secs()),
+            None,
+            Some("成功登录".as_bytes().to_vec()));
+        reply.set_token(token);
+        reply
+    }
+
+    //把客户端的文件名转换为根目录下规范化的路径，name为空时就是根目录
+    //经过根目录内的符号链接、./a.txt和a.txt等不同写法的同一个文件得到同一个路径，缓存和访问控制都用它作为键
+    //绝对路径和..直接拒绝；路径上的符号链接指向根目录外面，或者是无法解析的符号链接，同样拒绝
+    fn resolve(&self, name:&str) -> Result<String, Status> {
+        let mut path = self.root.clone();
+        for component in Path::new(name).components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => {},
+                Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(Status::PermissionDenied),
+            }
+        }
+        //还不存在的部分不会是符号链接，只需要规范化已经存在的最长前缀，再接上不存在的部分
+        let mut existing = path.as_path();
+        while fs::symlink_metadata(existing).is_err() {
+            existing = match existing.parent() {
+                Some(parent) => parent,
+                None => return Err(Status::PermissionDenied),
+            };
+        }
+        match fs::canonicalize(existing) {
+            Ok(real) if real.starts_with(&self.root) => {
+                //整个路径都已经存在时不再拼接，否则会多出结尾的/
+                let rest = path.strip_prefix(existing).map_err(|_| Status::PermissionDenied)?;
+                let real = if rest.as_os_str().is_empty() { real } else { real.join(rest) };
+                Ok(real.to_string_lossy().into_owned())
+            },
+            _ => Err(Status::PermissionDenied),
+        }
+    }
+
+    //设置写策略，从写回切换为写穿时先把缓存中的修改写回磁盘
+    pub fn set_write_policy(&mut self, write_policy:WritePolicy) {
+        if write_policy == WritePolicy::WriteThrough {
+            self.flush_cache();
+        }
+        self.write_policy = write_policy;
+    }
+
+    //把服务器缓存中还没有写回的文件写到磁盘上
+    pub fn flush_cache(&mut self) {
+        match self.server_cache.flush() {
+            Ok(0) => {},
+            Ok(flushed) => println!("写回{}个文件",flushed),
+            Err(e) => println!("写回文件失败:{}",e),
+        }
+        self.last_flush = Instant::now();
+    }
+
+    //只把目录dir下还没有写回的文件写到磁盘上，缓存中的其他文件不受影响
+    fn flush_dir(&mut self, dir:&str) {
+        let prefix = format!("{}/", dir);
+        if let Err(e) = self.server_cache.flush_where(|key| key.starts_with(&prefix)) {
+            println!("写回文件失败:{}",e);
+        }
+    }
+
+    //列出目录，写回模式下只在缓存中的文件也列出，缓存中的长度和修改时间比磁盘上的新
+    fn list_dir(&self, dir:&str) -> io::Result<Vec<DirEntry>> {
+        let mut entries = dir::list_dir(dir)?;
+        for info in self.server_cache.snapshot() {
+            let key = Path::new(&info.key);
+            let file = match self.server_cache.peek(&info.key) {
+                Some(file) if file.is_dirty() && key.parent() == Some(Path::new(dir)) => file,
+                _ => continue,
+            };
+            let name = match key.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let entry = DirEntry { name, kind:EntryKind::File, size:file.read_length() as u64, modified:file.read_modified() };
+            match entries.iter_mut().find(|listed| listed.name == entry.name) {
+                Some(listed) => *listed = entry,
+                None => entries.push(entry),
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    //写回模式下到了写回的时间就把缓存写回磁盘
+    fn flush_if_due(&mut self) {
+        if let WritePolicy::WriteBack(interval) = self.write_policy {
+            if self.last_flush.elapsed() >= interval {
+                self.flush_cache();
+            }
+        }
+    }
+
+    //保存修改过的文件
+    //写穿时先写回磁盘再放入缓存；写回时只放入缓存，放不进缓存的文件直接写回磁盘
+    fn save_file(&mut self, mut file:TFile) -> io::Result<()> {
+        let write_back = matches!(self.write_policy, WritePolicy::WriteBack(_));
+        if !write_back {
+            file.flush()?;
+        }
+        if !self.server_cache.put(file.read_name(), file.clone()) && write_back {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    //关闭服务器的标志，可以在别的线程或者信号处理中设置
+    pub fn shutdown_flag(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    //设置模拟的网络故障，outgoing作用于发出的回复，incoming作用于收到的请求
+    pub fn set_faults(&mut self, outgoing:Faults, incoming:Faults, seed:u64) {
+        self.socket.set_faults(outgoing, incoming, seed);
     }
 
     //服务器运行
-    pub fn run(&mut self) {
+    //循环接收客户端的请求，解析后把回复发回客户端，设置了关闭的标志后返回
+    pub fn run(&mut self) -> io::Result<()> {
+        println!("服务器运行在{}，调用语义{:?}，写策略{:?}",self.address,self.semantics,self.write_policy);
+        //等待请求最多等到下一次写回的时间，并且定时检查关闭的标志
+        let timeout = match self.write_policy {
+            WritePolicy::WriteBack(interval) => interval.clamp(Duration::from_millis(1), SHUTDOWN_POLL),
+            WritePolicy::WriteThrough => SHUTDOWN_POLL,
+        };
+        self.socket.set_read_timeout(Some(timeout))?;
+        loop {
+            if self.shutdown.load(Ordering::SeqCst) {
+                println!("服务器关闭");
+                return Ok(());
+            }
+            self.flush_if_due();
+            let (serial_message,address) = match self.receive_serial_message() {
+                Ok(received) => received,
+                //超时或者被信号打断时回到循环开头，检查是否要关闭和写回
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted) => continue,
+                Err(e) => return Err(e),
+            };
+            println!("{}",serial_message.summary());
+            let id = serial_message.read_id();
+            let filter = self.semantics == Semantics::AtMostOnce
+                && Self::is_non_idempotent(serial_message.read_operation());
 
+            //至多一次语义下，已经执行过的请求直接重发之前的回复
+            if filter {
+                if let Some(reply) = self.recorded_reply(&address, id) {
+                    println!("重复的请求{}:{}，重发回复",address,id);
+                    if let Err(e) = self.send_serial_message(reply,address) {
+                        println!("{:?}",e);
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(mut reply) = self.parse_operation(serial_message, address.clone()) {
+                reply.set_id(id);
+                if filter {
+                    self.record_reply(address.clone(), id, reply.clone());
+                }
+                println!("{}",reply.summary());
+                let res = self.send_serial_message(reply,address);
+                if let Err(e) = res {
+                    println!("{:?}",e);
+                }
+            }
+        }
+    }
+
+    //至多一次语义下记录过的回复
+    fn recorded_reply(&self, address:&str, id:u32) -> Option<SerialMessage> {
+        self.history.get(address)?.iter().find(|(recorded, _)| *recorded == id).map(|(_, reply)| reply.clone())
+    }
+
+    //记录非幂等请求的回复，超过HISTORY_PER_CLIENT条时丢弃这个客户端最早的回复
+    fn record_reply(&mut self, address:String, id:u32, reply:SerialMessage) {
+        let replies = self.history.entry(address).or_default();
+        if replies.len() >= HISTORY_PER_CLIENT {
+            replies.pop_front();
+        }
+        replies.push_back((id, reply));
+    }
+
+    //非幂等的操作，重复执行结果不同，需要在至多一次语义下过滤
+    //创建文件重复执行会回复文件已存在，插入重复执行会插入多次，删除和重命名重复执行会回复找不到文件
+    //目录的创建和删除同理，登录重复执行会得到不同的令牌
+    fn is_non_idempotent(operation:Operation) -> bool {
+        matches!(operation, Operation::Create | Operation::Insert | Operation::Delete | Operation::Rename
+            | Operation::MakeDir | Operation::RemoveDir | Operation::Login)
+    }
+
+    //生成操作数=6的回复，content是直接打印的信息
+    fn message_reply(status:Status, message:&str) -> SerialMessage {
+        SerialMessage::reply(status,
+            "message".to_string(),
+            Some(message.as_bytes().to_vec()))
+    }
+
+    //只有状态码的回复，信息使用状态码默认的说明
+    fn status_reply(status:Status) -> SerialMessage {
+        Self::message_reply(status, status.describe())
+    }
+
+    //取出文件，优先从服务器缓存中读取
+    //缓存中没有则从磁盘读取，并放入缓存
+    fn load_file(&mut self, name:String) -> io::Result<TFile> {
+        self.forget_deleted(&name);
+        if let Ok(file) = self.server_cache.get(name.clone()) {
+            return Ok(file);
+        }
+        let file = TFile::open(name.clone())?;
+        self.server_cache.put(name, file.clone());
+        Ok(file)
+    }
+
+    //磁盘上的文件被其他程序删除后，把它移出服务器缓存，否则会一直读到已经删除的文件
+    //写回模式下还没有写到磁盘上的文件不算被删除
+    fn forget_deleted(&mut self, name:&str) {
+        let saved = self.server_cache.peek(name).map(|file| !file.is_dirty()).unwrap_or(false);
+        if saved && !fs::metadata(name).map(|m| m.is_file()).unwrap_or(false) {
+            self.server_cache.invalidate(name);
+        }
     }
 
     //接收信息 接收的信息包括，反序列化的信息和地址
+    //无法解析的信息直接回复错误信息，然后继续等待下一条
     pub fn receive_serial_message(&mut self) -> io::Result<(SerialMessage,String)>{
-        // buf : UDP发送信息的容量
-        let mut buf = [0u8; 1000];
+        // buf : UDP发送信息的容量，一个UDP数据报最大不超过65535字节
+        let mut buf = vec![0u8; 65535];
 
-        //amt：UDP具体获得的信息数, src: 客户端地址
-        let (amt, src) = self.socket.recv_from(&mut buf)?;
+        loop {
+            //amt：UDP具体获得的信息数, src: 客户端地址
+            let (amt, src) = self.socket.recv_from(&mut buf)?;
 
-        //把客户端地址写入服务端
-        self.client_address.insert(src.to_string());
-        //buf 接收的具体长度
-        let buf = &mut buf[..amt];
-        //self.socket.send_to(buf, &src)?;
-        Ok((SerialMessage::deserialize(buf.to_vec()),src.to_string()))
+            //把客户端地址写入服务端
+            self.client_address.insert(src.to_string());
+            //buf 接收的具体长度
+            let buf = &buf[..amt];
+            match SerialMessage::deserialize(buf.to_vec()) {
+                Ok(serial_message) => return Ok((serial_message,src.to_string())),
+                Err(e) => {
+                    println!("无法解析来自{}的信息:{}",src,e);
+                    let mut reply = Self::message_reply(Status::BadRequest, &format!("无法解析的请求:{}",e));
+                    reply.set_id(SerialMessage::peek_id(buf).unwrap_or(0));
+                    if let Err(e) = self.send_serial_message(reply, src.to_string()) {
+                        println!("{:?}",e);
+                    }
+                },
+            }
+        }
+    }
+
+    //查询文件的长度和最后修改的时间
+    //缓存中有则直接用TFile的信息，否则从磁盘的元数据读取，不需要把文件读入缓存
+    fn stat_file(&mut self, name:String) -> Option<(usize,u64)> {
+        self.forget_deleted(&name);
+        if let Ok(file) = self.server_cache.get(name.clone()) {
+            return Some((file.read_length(), file.read_modified()));
+        }
+        match fs::metadata(&name) {
+            Ok(metadata) if metadata.is_file() => {
+                let modified = metadata.modified().map(file::millis).unwrap_or(0);
+                Some((metadata.len() as usize, modified))
+            },
+            _ => None,
+        }
+    }
+
+    //检查客户端对文件有没有permission中的全部权限
+    fn check_access(&self, path:&str, client:&str, permission:u64) -> Result<(), Status> {
+        match self.acls.get(path) {
+            Some(acl) if !acl.allows(client, permission) => Err(Status::PermissionDenied),
+            _ => Ok(()),
+        }
+    }
+
+    //是否是服务器自己使用的文件
+    fn is_hidden(&self, path:&str) -> bool {
+        self.hidden.iter().any(|hidden| hidden == Path::new(path))
+    }
+
+    //文件是否存在，写回模式下可能只在服务器缓存中
+    fn file_exists(&self, name:&str) -> bool {
+        self.server_cache.find(name.to_string()) || fs::metadata(name).map(|m| m.is_file()).unwrap_or(false)
     }
 
     //解析操作
+    //name是客户端的文件名，回复和通知客户端时使用；path是根目录下规范化的路径，访问缓存、磁盘和记录客户端时使用
+    //address是客户端的地址，记录缓存和监控的客户端时使用；client是客户端的身份，访问控制时使用
     fn parse_operation(&mut self,serial_message:SerialMessage,address:String) -> Option<SerialMessage>{
+        match serial_message.read_operation() {
+            //客户端不应该发送回复和缓存失效，直接忽略
+            Operation::Message | Operation::Invalidate => return None,
+            //登录时还没有令牌，name是用户名不是文件名
+            Operation::Login => return Some(self.login(&serial_message, &address)),
+            _ => {},
+        }
+        let client = match self.authenticate(&serial_message, &address) {
+            Ok(client) => client,
+            Err(status) => return Some(Self::status_reply(status)),
+        };
+        let name = serial_message.read_name();
+        let path = match self.resolve(&name) {
+            Ok(path) => path,
+            Err(status) => return Some(Self::status_reply(status)),
+        };
+        if self.is_hidden(&path) {
+            return Some(Self::status_reply(Status::PermissionDenied));
+        }
+        //文件操作需要的权限，创建文件时还没有访问控制，解锁文件时检查密码
+        let permission = match serial_message.read_operation() {
+            Operation::Read | Operation::Length | Operation::Modified | Operation::Callback => PERMISSION_READ,
+            Operation::Insert | Operation::Delete | Operation::Rename => PERMISSION_WRITE,
+            _ => 0,
+        };
+        if let Err(status) = self.check_access(&path, &client, permission) {
+            return Some(Self::status_reply(status));
+        }
         match serial_message.read_operation() {
 
-            //当操作数=1的时候，是创建文件的操作
-            1=>{
+            //创建文件的操作
+            Operation::Create=>{
                 //首先查找是否文件已经存在
-                //先检查服务器cache中是否存在
-                if self.server_cache.find(serial_message.read_name()) {
-                    let reply = SerialMessage::new(6,
-                        "message".to_string(),
-                        None,
-                        None,
-                        None,
-                        Some("服务器上已经存在该文件".as_bytes().to_vec()));
-                    let mut res = self.send_serial_message(reply.clone(), address.clone());
-                    while let Err(err) = res {
-                        println!("网络阻塞，需要重试{}",err);
-                        res = self.send_serial_message(reply.clone(), address.clone());
-                    }
-                    return None;
+                //先检查服务器cache中是否存在，再检查磁盘上是否存在
+                if self.server_cache.find(path.clone()) || fs::metadata(&path).is_ok() {
+                    return Some(Self::status_reply(Status::AlreadyExists));
                 }
-                //再检查内存中是否存在
-                else if let Ok(metadata) = fs::metadata(serial_message.read_name()) {
-                    if metadata.is_file() {
-                        let reply = SerialMessage::new(6,
-                            "message".to_string(),
-                            None,
+                //上一级目录必须已经存在，写回模式下文件先只放在缓存里，否则要到写回时才会失败
+                if !Path::new(&path).parent().map(Path::is_dir).unwrap_or(false) {
+                    return Some(Self::status_reply(Status::NotFound));
+                }
+                //创建文件的客户端是所有者，number是其他客户端的权限，sequence是解锁的密码
+                let cipher = match serial_message.read_sequence() {
+                    Some(password) => match Cipher::new(&password) {
+                        Ok(cipher) => Some(cipher),
+                        Err(e) => return Some(Self::message_reply(Status::BadRequest, &format!("创建文件失败{}",e))),
+                    },
+                    None => None,
+                };
+                let others = serial_message.read_number().unwrap_or(PERMISSION_ALL);
+                let file = TFile::new_unsaved(path.clone(), serial_message.read_content());
+                let modified = file.read_modified();
+                if let Err(e) = self.save_file(file) {
+                    return Some(Self::message_reply(Status::BadRequest, &format!("创建文件失败{}",e)));
+                }
+                self.add_reader(path.clone(), name, address);
+                self.acls.insert(path, Acl::new(client, others, cipher));
+                self.save_acls();
+                println!("{}",self.server_cache.stats());
+                let mut reply = Self::message_reply(Status::Ok, "成功创建文件");
+                reply.set_modified(modified);
+                Some(reply)
+            },
+
+            //读取文件的操作
+            //从offset开始读取number个字节，没有number则读到文件末尾
+            //一次最多回复MAX_READ_BYTES个字节，超过一个数据报的回复无法发送，剩下的部分客户端再次读取
+            Operation::Read=>{
+                let file = match self.load_file(path.clone()) {
+                    Ok(file) => file,
+                    Err(_) => {
+                        return Some(Self::status_reply(Status::NotFound));
+                    }
+                };
+                let offset = serial_message.read_offset().unwrap_or(0);
+                let number = serial_message.read_number().unwrap_or(MAX_READ_BYTES).min(MAX_READ_BYTES);
+                match file.read_bytes(offset, Some(number)) {
+                    Some(content) => {
+                        self.add_reader(path, name.clone(), address);
+                        let mut reply = SerialMessage::new(Operation::Message,
+                            name,
+                            Some(offset),
                             None,
                             None,
-                            Some("服务器上已经存在该文件".as_bytes().to_vec()));
-                        let mut res = self.send_serial_message(reply.clone(), address.clone());
-                        while let Err(err) = res {
-                            println!("网络阻塞，需要重试{}",err);
-                            res = self.send_serial_message(reply.clone(), address.clone());
-                        }
-                        return None;
+                            Some(content));
+                        reply.set_modified(file.read_modified());
+                        Some(reply)
+                    },
+                    None => Some(Self::status_reply(Status::OutOfRange)),
+                }
+            },
+            //在offset处插入sequence的操作
+            //非幂等操作，修改磁盘上的文件和服务器缓存
+            //回复插入后的长度和修改时间，不回复文件内容：超过一个数据报的文件会发送失败，而插入已经执行了
+            Operation::Insert=>{
+                let mut file = match self.load_file(path.clone()) {
+                    Ok(file) => file,
+                    Err(_) => {
+                        return Some(Self::status_reply(Status::NotFound));
                     }
+                };
+                let offset = serial_message.read_offset().unwrap_or(0);
+                let sequence = serial_message.read_sequence().unwrap_or_default();
+                if file.insert_bytes(offset, sequence).is_err() {
+                    return Some(Self::status_reply(Status::OutOfRange));
                 }
-                else {
-                    let file = TFile::new(serial_message.read_name(), serial_message.read_content()).unwrap();
-                    self.server_cache.put(serial_message.read_name(), file);
-                    println!("{:?}",self.server_cache.map.keys());
-                    return Some(SerialMessage::new(6,
-                        "message".to_string(),
-                        None,
-                        None,
-                        None,
-                        Some("成功创建文件".as_bytes().to_vec())))
+                if let Err(e) = self.save_file(file.clone()) {
+                    return Some(Self::message_reply(Status::BadRequest, &format!("写入文件失败{}",e)));
                 }
-                None
+                self.notify_monitors(path.clone(), &file);
+                //插入的客户端收到回复后自己重新读取，其他客户端的缓存失效
+                self.invalidate_readers(path.clone(), &address);
+                self.add_reader(path, name.clone(), address);
+                let mut reply = SerialMessage::new(Operation::Message,
+                    name,
+                    None,
+                    Some(file.read_length() as u64),
+                    None,
+                    None);
+                reply.set_modified(file.read_modified());
+                Some(reply)
             },
-
-            2=>{None},
-            3=>{None},
-            4=>{None},
-            5=>{None},
-            6=>{None},
-            _=>{None},
+            //计算文件长度的操作
+            //幂等操作
+            Operation::Length=>{
+                let (length, modified) = match self.stat_file(path) {
+                    Some(stat) => stat,
+                    None => return Some(Self::status_reply(Status::NotFound)),
+                };
+                let mut reply = SerialMessage::new(Operation::Message,
+                    name,
+                    None,
+                    Some(length as u64),
+                    None,
+                    Some(length.to_string().as_bytes().to_vec()));
+                reply.set_modified(modified);
+                Some(reply)
+            },
+            //查询最后修改时间的操作
+            //幂等操作，客户端缓存过期后用它判断服务器上的文件有没有被修改
+            Operation::Modified=>{
+                let (_, modified) = match self.stat_file(path) {
+                    Some(stat) => stat,
+                    None => return Some(Self::status_reply(Status::NotFound)),
+                };
+                let mut reply = SerialMessage::reply(Status::Ok,
+                    name,
+                    Some(modified.to_string().as_bytes().to_vec()));
+                reply.set_modified(modified);
+                Some(reply)
+            },
+            //查询服务器缓存统计数据的操作
+            //幂等操作，回复统计数据和按最近访问顺序排列的缓存文件，每行一项
+            Operation::Stats=>{
+                let mut lines = vec![self.server_cache.stats().to_string()];
+                //缓存中是根目录下的完整路径，显示时去掉根目录
+                let root = format!("{}/", self.root.display());
+                lines.extend(self.server_cache.snapshot().into_iter().map(|mut entry| {
+                    if let Some(name) = entry.key.strip_prefix(&root) {
+                        entry.key = name.to_string();
+                    }
+                    entry.to_string()
+                }));
+                Some(Self::message_reply(Status::Ok, &lines.join("\n")))
+            },
+            //注册监控文件的操作
+            //number是监控的时长（秒），在这段时间内文件被修改会推送给客户端
+            Operation::Callback=>{
+                if !self.file_exists(&path) {
+                    return Some(Self::status_reply(Status::NotFound));
+                }
+                //时长太大时截取到u32的范围，否则计算截止时间会溢出
+                let interval = serial_message.read_number().unwrap_or(0).min(u32::MAX as u64);
+                let deadline = Instant::now() + Duration::from_secs(interval);
+                let monitors = self.monitors.entry(path).or_default();
+                monitors.retain(|(client, _, _)| client != &address);
+                monitors.push((address, name, deadline));
+                Some(Self::message_reply(Status::Ok, "成功注册监控"))
+            },
+            //删除文件的操作
+            //非幂等操作，删除磁盘上的文件和服务器缓存，通知缓存了该文件的其他客户端
+            Operation::Delete=>{
+                if !self.file_exists(&path) {
+                    return Some(Self::status_reply(Status::NotFound));
+                }
+                //写回模式下新创建的文件可能还没有写到磁盘上
+                let cached = self.server_cache.invalidate(&path).is_some();
+                match fs::remove_file(&path) {
+                    Ok(()) => {},
+                    Err(e) if e.kind() == io::ErrorKind::NotFound && cached => {},
+                    Err(e) => return Some(Self::message_reply(Status::BadRequest, &format!("删除文件失败{}",e))),
+                }
+                if self.acls.remove(&path).is_some() {
+                    self.save_acls();
+                }
+                self.forget_file(path, &address);
+                Some(Self::message_reply(Status::Ok, "成功删除文件"))
+            },
+            //重命名文件的操作
+            //非幂等操作，content是新的文件名，旧文件名对其他客户端来说和删除一样
+            Operation::Rename=>{
+                let new_name = String::from_utf8_lossy(&serial_message.read_content()).to_string();
+                if new_name.is_empty() {
+                    return Some(Self::message_reply(Status::BadRequest, "没有新的文件名"));
+                }
+                let new_path = match self.resolve(&new_name) {
+                    Ok(new_path) if !self.is_hidden(&new_path) => new_path,
+                    Ok(_) => return Some(Self::status_reply(Status::PermissionDenied)),
+                    Err(status) => return Some(Self::status_reply(status)),
+                };
+                if !self.file_exists(&path) {
+                    return Some(Self::status_reply(Status::NotFound));
+                }
+                if self.file_exists(&new_path) {
+                    return Some(Self::status_reply(Status::AlreadyExists));
+                }
+                //先把还没有写回的修改写到磁盘上，再重命名磁盘上的文件
+                //写回成功后才移出缓存，失败时缓存中还是没有写回的内容，不会丢失
+                if let Some(mut file) = self.server_cache.peek(&path).cloned() {
+                    if let Err(e) = file.flush() {
+                        return Some(Self::message_reply(Status::BadRequest, &format!("重命名文件失败{}",e)));
+                    }
+                    self.server_cache.invalidate(&path);
+                }
+                if let Err(e) = fs::rename(&path, &new_path) {
+                    return Some(Self::message_reply(Status::BadRequest, &format!("重命名文件失败{}",e)));
+                }
+                //访问控制跟着文件走
+                match self.acls.remove(&path) {
+                    Some(acl) => { self.acls.insert(new_path.clone(), acl); },
+                    None => { self.acls.remove(&new_path); },
+                }
+                self.save_acls();
+                self.forget_file(path, &address);
+                let mut reply = Self::message_reply(Status::Ok, "成功重命名文件");
+                if let Some((_, modified)) = self.stat_file(new_path) {
+                    reply.set_modified(modified);
+                }
+                Some(reply)
+            },
+            //创建目录的操作，上一级目录必须已经存在
+            Operation::MakeDir=>{
+                if name.is_empty() {
+                    return Some(Self::message_reply(Status::BadRequest, "没有目录名"));
+                }
+                match fs::create_dir(&path) {
+                    Ok(()) => Some(Self::message_reply(Status::Ok, "成功创建目录")),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Some(Self::status_reply(Status::AlreadyExists)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Some(Self::status_reply(Status::NotFound)),
+                    Err(e) => Some(Self::message_reply(Status::BadRequest, &format!("创建目录失败{}",e))),
+                }
+            },
+            //删除目录的操作，只能删除空目录
+            //写回模式下目录中可能有还没写到磁盘上的文件，先写回这个目录下的文件再判断是否为空
+            Operation::RemoveDir=>{
+                if name.is_empty() {
+                    return Some(Self::message_reply(Status::BadRequest, "没有目录名"));
+                }
+                self.flush_dir(&path);
+                if !fs::metadata(&path).map(|m| m.is_dir()).unwrap_or(false) {
+                    return Some(Self::status_reply(Status::NotFound));
+                }
+                match fs::remove_dir(&path) {
+                    Ok(()) => Some(Self::message_reply(Status::Ok, "成功删除目录")),
+                    Err(e) => Some(Self::message_reply(Status::BadRequest, &format!("删除目录失败{}",e))),
+                }
+            },
+            //列出目录的操作，name为空时列出导出的根目录
+            //幂等操作，从第offset项开始最多返回number项，回复的number是目录的总项数
+            //客户端没有读权限的文件、指向根目录外面的符号链接和服务器自己使用的文件不列出
+            Operation::ListDir=>{
+                let mut entries = match self.list_dir(&path) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Some(Self::status_reply(Status::NotFound)),
+                    Err(e) => return Some(Self::message_reply(Status::BadRequest, &format!("列出目录失败{}",e))),
+                };
+                entries.retain(|entry| {
+                    match self.resolve(&Path::new(&name).join(&entry.name).to_string_lossy()) {
+                        Ok(entry_path) => !self.is_hidden(&entry_path) && self.check_access(&entry_path, &client, PERMISSION_READ).is_ok(),
+                        Err(_) => false,
+                    }
+                });
+                let offset = serial_message.read_offset().unwrap_or(0);
+                if offset > entries.len() as u64 {
+                    return Some(Self::status_reply(Status::OutOfRange));
+                }
+                let number = serial_message.read_number().unwrap_or(LIST_PAGE);
+                let (content, _) = dir::encode_page(&entries[offset as usize..], number as usize);
+                Some(SerialMessage::new(Operation::Message,
+                    name,
+                    Some(offset),
+                    Some(entries.len() as u64),
+                    None,
+                    Some(content)))
+            },
+            //解锁文件的操作，content是密码，number是申请的权限
+            //幂等操作，密码正确时把客户端加入文件的访问控制列表；没有访问控制的文件不需要解锁
+            Operation::Unlock=>{
+                if !self.file_exists(&path) {
+                    return Some(Self::status_reply(Status::NotFound));
+                }
+                let permission = serial_message.read_number().unwrap_or(PERMISSION_ALL);
+                let unlocked = match self.acls.get_mut(&path) {
+                    Some(acl) => acl.unlock(&client, &serial_message.read_content(), permission),
+                    None => true,
+                };
+                if !unlocked {
+                    return Some(Self::status_reply(Status::PermissionDenied));
+                }
+                self.save_acls();
+                Some(Self::message_reply(Status::Ok, "成功解锁文件"))
+            },
+            //退出登录的操作，令牌失效
+            Operation::Logout=>{
+                if let Some(token) = serial_message.read_token() {
+                    self.sessions.logout(token, &address);
+                }
+                Some(Self::message_reply(Status::Ok, "成功退出登录"))
+            },
+            //上面已经处理过
+            Operation::Message | Operation::Invalidate | Operation::Login=>{None},
         }
     }
     
+    //记录客户端缓存了文件，name是客户端使用的文件名
+    fn add_reader(&mut self, path:String, name:String, address:String) {
+        self.readers.entry(path).or_default().insert(address, name);
+    }
+
+    //通知缓存了文件的客户端（除了修改文件的客户端）缓存失效
+    //通知只发送一次不等回复，丢失时客户端依靠缓存有效期兜底
+    //通知过的客户端不再记录，重新读取后才会再次记录
+    fn invalidate_readers(&mut self, path:String, writer:&str) {
+        self.notify_readers(Operation::Invalidate, path, writer);
+    }
+
+    //文件被删除或者重命名后，通知缓存了文件的其他客户端把它移出缓存，不再监控这个文件
+    fn forget_file(&mut self, path:String, writer:&str) {
+        self.monitors.remove(&path);
+        self.notify_readers(Operation::Delete, path, writer);
+    }
+
+    //通知里是每个客户端自己使用的文件名
+    fn notify_readers(&mut self, operation:Operation, path:String, writer:&str) {
+        let readers = match self.readers.remove(&path) {
+            Some(readers) => readers,
+            None => return,
+        };
+        for (reader, name) in readers.into_iter().filter(|(reader, _)| reader != writer) {
+            let notice = SerialMessage::new(operation, name, None, None, None, None);
+            if let Err(e) = self.send_serial_message(notice, reader) {
+                println!("{:?}",e);
+            }
+        }
+    }
+
+    //通知正在监控该文件的客户端文件被修改了，同时清理已经过期的监控
+    //推送里只有新的长度和修改时间，文件可能放不进一个数据报，客户端收到后自己按块读取
+    fn notify_monitors(&mut self, path:String, file:&TFile) {
+        let now = Instant::now();
+        let clients = match self.monitors.get_mut(&path) {
+            Some(monitors) => {
+                monitors.retain(|(_, _, deadline)| *deadline > now);
+                monitors.iter().map(|(client, name, _)| (client.clone(), name.clone())).collect::<Vec<(String,String)>>()
+            },
+            None => return,
+        };
+        if clients.is_empty() {
+            self.monitors.remove(&path);
+            return;
+        }
+        for (client, name) in clients {
+            let mut update = SerialMessage::new(Operation::Callback,
+                name,
+                None,
+                Some(file.read_length() as u64),
+                None,
+                None);
+            update.set_modified(file.read_modified());
+            if let Err(e) = self.send_serial_message(update, client) {
+                println!("{:?}",e);
+            }
+        }
+    }
+
     //发送信息
     //参数为Serial_message和客户端的地址
     pub fn send_serial_message(&mut self,serial_message:SerialMessage,address:String) ->io::Result<()> {
         let serial_vec = serial_message.serialize_message();
-        self.socket.send_to(&mut serial_vec.as_slice(), address)?;
+        self.socket.send_to(serial_vec.as_slice(), address)?;
         Ok(())
     }
 }
 
+//服务器关闭时把缓存中还没有写回的文件写回磁盘
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.flush_cache();
+    }
+}
+
 fn main() -> std::io::Result<()> {
     //绑定端口
     //建立服务端对象
     //包含缓存等信息
-    let mut server = Server::new("127.0.0.1:8080".to_string(),2);
-
-    loop {
-        // // buf : capacity
-        // let mut buf = [0u8; 100];
-
-        // //amt：number of bytes, src: address
-        // let (amt, src) = server.socket.recv_from(&mut buf)?;
-
-        // //buf is concrete length of recv
-        // let buf = &mut buf[..amt];
-        // server.socket.send_to(buf, &src)?;
-        // let a = SerialMessage::deserialize(buf.to_vec());
-        let a = server.receive_serial_message()?;
-        let b = a.clone();
-        let c = server.parse_operation(b.0, b.1);
-        if let Some(d) = c {
-            println!("{:?}",d.clone());
-            let res = server.send_serial_message(d,a.1.clone());
-            if let Err(e) = res {
-                println!("{:?}",e);
-            }
-        } 
-        println!("{:?}", String::from_utf8(a.0.clone().read_content()).unwrap());
-        //println!("{:?}", server.server_cache.map);
+    //默认至多一次语义，参数at-least-once切换为至少一次语义
+    let semantics = if std::env::args().any(|arg| arg == "at-least-once") {
+        Semantics::AtLeastOnce
+    } else {
+        Semantics::AtMostOnce
+    };
+    //参数policy=lru/lfu/2q/fifo，选择服务器缓存的淘汰策略
+    let policy = std::env::args().find_map(|arg| arg.strip_prefix("policy=").and_then(|p| p.parse::<Policy>().ok()));
+    let mut server = match policy {
+        Some(policy) => Server::with_policy("127.0.0.1:8080".to_string(),2,semantics,policy),
+        None => Server::new("127.0.0.1:8080".to_string(),2,semantics),
+    };
+    //参数cache-bytes=字节数，按字节限制服务器缓存的容量
+    if let Some(budget) = std::env::args().find_map(|arg| arg.strip_prefix("cache-bytes=").and_then(|b| b.parse().ok())) {
+        server.server_cache = LRUCache::with_byte_budget(budget);
+        server.server_cache.set_policy(policy.unwrap_or_default());
+    }
+    //参数write-back=秒数，服务器缓存使用写回模式，每隔这么多秒写回一次磁盘
+    if let Some(interval) = std::env::args().find_map(|arg| arg.strip_prefix("write-back=").and_then(|s| s.parse().ok())) {
+        server.set_write_policy(WritePolicy::WriteBack(Duration::from_secs(interval)));
+    }
+    //参数root=目录，只导出这个目录下的文件，默认是当前目录
+    if let Some(root) = std::env::args().find_map(|arg| arg.strip_prefix("root=").map(str::to_string)) {
+        server.set_root(root)?;
+    }
+    //参数users=文件，从文件读取用户，有用户时开启身份认证
+    //参数adduser=用户名:密码，添加用户并保存到users指定的文件中
+    //指定了users但是文件不存在或者没有用户时不启动，否则文件被删掉后服务器会悄悄关闭身份认证
+    if let Some(path) = std::env::args().find_map(|arg| arg.strip_prefix("users=").map(str::to_string)) {
+        let mut credentials = Credentials::load(&path)?;
+        for (name, password) in std::env::args().filter_map(|arg| arg.strip_prefix("adduser=")
+            .and_then(|user| user.split_once(':')).map(|(name, password)| (name.to_string(), password.to_string()))) {
+            credentials.add_user(&name, password.as_bytes())?;
+            credentials.save(&path)?;
+        }
+        if credentials.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}中没有用户，用adduser=用户名:密码添加", path)));
+        }
+        server.set_credentials(credentials);
+        server.hide_file(&path)?;
+    }
+    //参数acls=文件，访问控制保存在这个文件中，服务器重启后仍然有效；文件中是相对路径，在设置根目录之后读取
+    if let Some(path) = std::env::args().find_map(|arg| arg.strip_prefix("acls=").map(str::to_string)) {
+        server.set_acl_file(path)?;
     }
+    //参数session=秒数，会话令牌的有效期
+    if let Some(ttl) = std::env::args().find_map(|arg| arg.strip_prefix("session=").and_then(|s| s.parse().ok())) {
+        server.set_session_ttl(Duration::from_secs(ttl));
+    }
+    //参数drop=概率，模拟收发时按概率丢包
+    if let Some(drop) = std::env::args().find_map(|arg| arg.strip_prefix("drop=").and_then(|p| p.parse().ok())) {
+        server.set_faults(Faults::lossy(drop), Faults::lossy(drop), 1);
+    }
+    //Ctrl-C时让run返回，server被释放时把写回模式下还没有写回的文件写到磁盘上
+    let shutdown = server.shutdown_flag();
+    ctrlc::set_handler(move || shutdown.store(true, Ordering::SeqCst)).map_err(io::Error::other)?;
+    server.run()
+}
+
+#[test]
+fn test_invocation_semantics() -> io::Result<()> {
+    use std::{sync::mpsc, thread};
+    //客户端发出的每个数据报都会重复，相当于插入请求被重传了一次
+    for (semantics, expected) in [(Semantics::AtMostOnce, "abXXcd"), (Semantics::AtLeastOnce, "abXXXXcd")] {
+        let name = format!("test_semantics_{:?}.txt", semantics);
+        fs::write(&name, "abcd")?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut server = Server::new("127.0.0.1:0".to_string(), 2, semantics);
+            tx.send(server.socket.local_addr().unwrap()).unwrap();
+            let _ = server.run();
+        });
+        let address = rx.recv().unwrap();
+
+        let duplicate = Faults { duplicate: 1.0, ..Faults::default() };
+        let mut client = Transport::with_faults(UdpSocket::bind("127.0.0.1:0")?, duplicate, Faults::default(), 1);
+        client.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let mut request = SerialMessage::new(Operation::Insert, name.clone(), Some(2), None, Some("XX".as_bytes().to_vec()), None);
+        request.set_id(1);
+        client.send_to(&request.serialize_message(), address)?;
+
+        let mut buf = [0u8; 1000];
+        for _ in 0..2 {
+            let (amt, _) = client.recv_from(&mut buf)?;
+            assert_eq!(SerialMessage::deserialize(buf[..amt].to_vec()).unwrap().read_id(), 1);
+        }
+        assert_eq!(fs::read_to_string(&name)?, expected);
+        fs::remove_file(&name)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_history_limit() {
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let (a, b) = ("127.0.0.1:1".to_string(), "127.0.0.1:2".to_string());
+    for id in 0..HISTORY_PER_CLIENT as u32 + 10 {
+        server.record_reply(a.clone(), id, Server::status_reply(Status::Ok));
+    }
+    server.record_reply(b.clone(), 0, Server::status_reply(Status::NotFound));
+
+    //每个客户端只保留最近的回复，不同客户端的编号互不影响
+    assert_eq!(server.history[&a].len(), HISTORY_PER_CLIENT);
+    assert!(server.recorded_reply(&a, 9).is_none());
+    assert!(server.recorded_reply(&a, 10).is_some());
+    assert_eq!(server.recorded_reply(&b, 0).unwrap().read_status(), Status::NotFound);
+    assert!(server.recorded_reply(&b, 1).is_none());
+}
+
+#[test]
+fn test_invalidate_readers() -> io::Result<()> {
+    use std::{sync::mpsc, thread};
+    let name = "test_invalidate.txt".to_string();
+    fs::write(&name, "abcd")?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+        tx.send(server.socket.local_addr().unwrap()).unwrap();
+        let _ = server.run();
+    });
+    let address = rx.recv().unwrap();
+
+    let reader = UdpSocket::bind("127.0.0.1:0")?;
+    let writer = UdpSocket::bind("127.0.0.1:0")?;
+    reader.set_read_timeout(Some(Duration::from_secs(1)))?;
+    writer.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut buf = [0u8; 1000];
+
+    //reader读取文件后被记录
+    let mut read = SerialMessage::new(Operation::Read, name.clone(), Some(0), None, None, None);
+    read.set_id(1);
+    reader.send_to(&read.serialize_message(), address)?;
+    reader.recv_from(&mut buf)?;
+
+    //writer插入后，reader收到缓存失效，writer只收到回复
+    let mut insert = SerialMessage::new(Operation::Insert, name.clone(), Some(0), None, Some("XX".as_bytes().to_vec()), None);
+    insert.set_id(1);
+    writer.send_to(&insert.serialize_message(), address)?;
+    let (amt, _) = writer.recv_from(&mut buf)?;
+    assert_eq!(SerialMessage::deserialize(buf[..amt].to_vec()).unwrap().read_operation(), Operation::Message);
+    let (amt, _) = reader.recv_from(&mut buf)?;
+    let invalidate = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+    assert_eq!(invalidate.read_operation(), Operation::Invalidate);
+    assert_eq!(invalidate.read_name(), name);
+
+    writer.set_read_timeout(Some(Duration::from_millis(100)))?;
+    assert!(writer.recv_from(&mut buf).is_err());
+    fs::remove_file(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_monitor_push() -> io::Result<()> {
+    use std::{sync::mpsc, thread};
+    let name = "test_monitor.txt".to_string();
+    fs::write(&name, "abcd")?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+        tx.send(server.socket.local_addr().unwrap()).unwrap();
+        let _ = server.run();
+    });
+    let address = rx.recv().unwrap();
+
+    let monitor = UdpSocket::bind("127.0.0.1:0")?;
+    let writer = UdpSocket::bind("127.0.0.1:0")?;
+    monitor.set_read_timeout(Some(Duration::from_secs(1)))?;
+    writer.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut buf = [0u8; 1000];
+
+    //注册监控5秒
+    let mut callback = SerialMessage::new(Operation::Callback, name.clone(), None, Some(5), None, None);
+    callback.set_id(1);
+    monitor.send_to(&callback.serialize_message(), address)?;
+    let (amt, _) = monitor.recv_from(&mut buf)?;
+    assert_eq!(SerialMessage::deserialize(buf[..amt].to_vec()).unwrap().read_status(), Status::Ok);
+
+    //writer插入后，monitor收到推送，推送里是新的长度和修改时间
+    let mut insert = SerialMessage::new(Operation::Insert, name.clone(), Some(0), None, Some("XX".as_bytes().to_vec()), None);
+    insert.set_id(1);
+    writer.send_to(&insert.serialize_message(), address)?;
+    let (amt, _) = writer.recv_from(&mut buf)?;
+    let reply = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+    let (amt, _) = monitor.recv_from(&mut buf)?;
+    let update = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+    assert_eq!((update.read_operation(), update.read_name()), (Operation::Callback, name.clone()));
+    assert_eq!(update.read_number(), Some(6));
+    assert_eq!(update.read_modified(), reply.read_modified());
+    assert!(update.read_content().is_empty());
+    fs::remove_file(&name)
+}
+
+#[test]
+fn test_insert_large_file() -> io::Result<()> {
+    //超过一个数据报的文件插入后只回复长度，回复仍然能发送
+    let name = "test_insert_large.txt".to_string();
+    fs::write(&name, vec![b'a'; 100000])?;
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let insert = SerialMessage::new(Operation::Insert, name.clone(), Some(0), None, Some("XX".as_bytes().to_vec()), None);
+    let reply = server.parse_operation(insert, "127.0.0.1:1".to_string()).unwrap();
+    assert_eq!((reply.read_status(), reply.read_number()), (Status::Ok, Some(100002)));
+    assert!(reply.serialize_message().len() < 1000);
+    assert_eq!(fs::metadata(&name)?.len(), 100002);
+
+    //读取时不管要求多少字节，一次最多回复MAX_READ_BYTES个字节
+    for number in [None, Some(100002)] {
+        let read = SerialMessage::new(Operation::Read, name.clone(), Some(0), number, None, None);
+        let reply = server.parse_operation(read, "127.0.0.1:1".to_string()).unwrap();
+        assert_eq!(reply.read_content().len(), MAX_READ_BYTES as usize);
+        assert!(reply.serialize_message().len() < 65507);
+    }
+    fs::remove_file(&name)
+}
+
+#[test]
+fn test_forget_deleted() -> io::Result<()> {
+    let name = "test_forget_deleted.txt".to_string();
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    server.server_cache.put(name.clone(), TFile::new(name.clone(), "abc".as_bytes().to_vec())?);
+    assert!(server.load_file(name.clone()).is_ok());
+
+    //文件被删除后不再从缓存中读到
+    fs::remove_file(&name)?;
+    assert!(server.load_file(name.clone()).is_err());
+    assert!(server.stat_file(name.clone()).is_none());
+    assert!(!server.server_cache.find(name));
+    Ok(())
+}
+
+#[test]
+fn test_write_back() -> io::Result<()> {
+    let names:Vec<String> = (0..3).map(|i| format!("test_write_back_{}.txt", i)).collect();
+    for name in &names {
+        let _ = fs::remove_file(name);
+    }
+    let address = "127.0.0.1:1".to_string();
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    server.set_write_policy(WritePolicy::WriteBack(Duration::from_secs(60)));
+
+    //创建和插入只修改服务器缓存
+    let create = SerialMessage::new(Operation::Create, names[0].clone(), None, None, None, Some("abcd".as_bytes().to_vec()));
+    server.parse_operation(create, address.clone());
+    let insert = SerialMessage::new(Operation::Insert, names[0].clone(), Some(2), None, Some("XX".as_bytes().to_vec()), None);
+    server.parse_operation(insert, address.clone());
+    assert!(fs::metadata(&names[0]).is_err());
+    assert_eq!(server.load_file(server.resolve(&names[0]).unwrap())?.read_content(), "abXXcd".as_bytes().to_vec());
+
+    //被淘汰时写回磁盘
+    for name in &names[1..] {
+        let create = SerialMessage::new(Operation::Create, name.clone(), None, None, None, Some(name.as_bytes().to_vec()));
+        server.parse_operation(create, address.clone());
+    }
+    assert_eq!(fs::read(&names[0])?, "abXXcd".as_bytes().to_vec());
+    assert!(fs::metadata(&names[2]).is_err());
+
+    //上一级目录不存在时不接受创建，不会留下写不回去的文件
+    let create = SerialMessage::new(Operation::Create, "test_write_back_missing/x.txt".to_string(), None, None, None, Some("x".as_bytes().to_vec()));
+    assert_eq!(server.parse_operation(create, address.clone()).unwrap().read_status(), Status::NotFound);
+    assert!(!server.server_cache.find(server.resolve("test_write_back_missing/x.txt").unwrap()));
+
+    //列出目录时缓存中的文件也列出，但不写回；删除目录时只写回这个目录下的文件
+    let dir = "test_write_back_dir".to_string();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir)?;
+    let create = SerialMessage::new(Operation::Create, format!("{}/a.txt", dir), None, None, None, Some("abcd".as_bytes().to_vec()));
+    server.parse_operation(create, address.clone());
+    let list = SerialMessage::new(Operation::ListDir, dir.clone(), None, None, None, None);
+    let entries = dir::decode_entries(&server.parse_operation(list, address.clone()).unwrap().read_content()).unwrap();
+    assert_eq!(entries.iter().map(|e| (e.name.as_str(), e.size)).collect::<Vec<_>>(), [("a.txt", 4)]);
+    assert!(fs::metadata(format!("{}/a.txt", dir)).is_err());
+    let remove = SerialMessage::new(Operation::RemoveDir, dir.clone(), None, None, None, None);
+    assert_eq!(server.parse_operation(remove, address.clone()).unwrap().read_status(), Status::BadRequest);
+    assert_eq!(fs::read(format!("{}/a.txt", dir))?, "abcd".as_bytes().to_vec());
+    assert!(fs::metadata(&names[2]).is_err());
+    fs::remove_dir_all(&dir)?;
+
+    //设置关闭的标志后run返回，服务器关闭时写回磁盘
+    server.shutdown_flag().store(true, Ordering::SeqCst);
+    server.run()?;
+    drop(server);
+    assert_eq!(fs::read(&names[2])?, names[2].as_bytes().to_vec());
+    for name in &names {
+        fs::remove_file(name)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_delete_rename() -> io::Result<()> {
+    let (name, new_name) = ("test_delete.txt".to_string(), "test_renamed.txt".to_string());
+    let _ = fs::remove_file(&new_name);
+    fs::write(&name, "abcd")?;
+    let reader = UdpSocket::bind("127.0.0.1:0")?;
+    reader.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let reader_address = reader.local_addr()?.to_string();
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let writer_address = "127.0.0.1:1".to_string();
+
+    //reader缓存了文件，重命名后收到删除通知
+    let read = SerialMessage::new(Operation::Read, name.clone(), Some(0), None, None, None);
+    server.parse_operation(read, reader_address);
+    let rename = SerialMessage::new(Operation::Rename, name.clone(), None, None, None, Some(new_name.as_bytes().to_vec()));
+    assert_eq!(server.parse_operation(rename, writer_address.clone()).unwrap().read_status(), Status::Ok);
+    let mut buf = [0u8; 1000];
+    let (amt, _) = reader.recv_from(&mut buf)?;
+    let notice = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+    assert_eq!((notice.read_operation(), notice.read_name()), (Operation::Delete, name.clone()));
+    assert!(!server.server_cache.find(server.resolve(&name).unwrap()));
+    assert_eq!(fs::read(&new_name)?, "abcd".as_bytes().to_vec());
+
+    //删除后磁盘和服务器缓存中都没有这个文件
+    let new_path = server.resolve(&new_name).unwrap();
+    assert!(server.load_file(new_path.clone()).is_ok());
+    let delete = SerialMessage::new(Operation::Delete, new_name.clone(), None, None, None, None);
+    assert_eq!(server.parse_operation(delete.clone(), writer_address.clone()).unwrap().read_status(), Status::Ok);
+    assert!(fs::metadata(&new_name).is_err());
+    assert!(!server.server_cache.find(new_path));
+    assert_eq!(server.parse_operation(delete, writer_address.clone()).unwrap().read_status(), Status::NotFound);
+
+    //写回失败时重命名失败，缓存中还没有写回的内容仍然保留
+    let path = server.resolve(&name).unwrap();
+    server.server_cache.put(path.clone(), TFile::new_unsaved(format!("{}/missing/x.txt", path), "abcd".as_bytes().to_vec()));
+    let rename = SerialMessage::new(Operation::Rename, name.clone(), None, None, None, Some(new_name.as_bytes().to_vec()));
+    assert_eq!(server.parse_operation(rename, writer_address).unwrap().read_status(), Status::BadRequest);
+    let file = server.server_cache.peek(&path).unwrap();
+    assert!(file.is_dirty());
+    assert_eq!(file.read_content(), "abcd".as_bytes().to_vec());
+    Ok(())
+}
+
+#[test]
+fn test_length() -> io::Result<()> {
+    let (on_disk, in_cache) = ("test_length_disk.txt".to_string(), "test_length_cache.txt".to_string());
+    let _ = fs::remove_file(&in_cache);
+    fs::write(&on_disk, "abcd")?;
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let address = "127.0.0.1:1".to_string();
+    let length = |server:&mut Server, name:&String| {
+        let reply = server.parse_operation(SerialMessage::new(Operation::Length, name.clone(), None, None, None, None), address.clone()).unwrap();
+        (reply.read_status(), reply.read_number())
+    };
+
+    //不在缓存中的文件从磁盘的元数据读取长度，不读入缓存
+    assert_eq!(length(&mut server, &on_disk), (Status::Ok, Some(4)));
+    assert!(!server.server_cache.find(server.resolve(&on_disk).unwrap()));
+
+    //写回模式下只在缓存中的文件用缓存中的长度
+    server.set_write_policy(WritePolicy::WriteBack(Duration::from_secs(60)));
+    let create = SerialMessage::new(Operation::Create, in_cache.clone(), None, None, None, Some("abcdef".as_bytes().to_vec()));
+    server.parse_operation(create, address.clone());
+    assert!(fs::metadata(&in_cache).is_err());
+    assert_eq!(length(&mut server, &in_cache), (Status::Ok, Some(6)));
+
+    fs::remove_file(&on_disk)?;
+    assert_eq!(length(&mut server, &on_disk), (Status::NotFound, None));
+    drop(server);
+    fs::remove_file(&in_cache)
+}
+
+#[test]
+fn test_directories() -> io::Result<()> {
+    let root = "test_directories".to_string();
+    let _ = fs::remove_dir_all(&root);
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let address = "127.0.0.1:1".to_string();
+    let request = |operation, name:String, offset, number| SerialMessage::new(operation, name, offset, number, None, None);
+
+    let reply = server.parse_operation(request(Operation::MakeDir, root.clone(), None, None), address.clone()).unwrap();
+    assert_eq!(reply.read_status(), Status::Ok);
+    let reply = server.parse_operation(request(Operation::MakeDir, root.clone(), None, None), address.clone()).unwrap();
+    assert_eq!(reply.read_status(), Status::AlreadyExists);
+    for name in ["a", "b", "c"] {
+        fs::write(format!("{}/{}", root, name), name)?;
+    }
+
+    //每页两项，第二页只剩一项
+    let reply = server.parse_operation(request(Operation::ListDir, root.clone(), Some(2), Some(2)), address.clone()).unwrap();
+    assert_eq!(reply.read_number(), Some(3));
+    let entries = dir::decode_entries(&reply.read_content()).unwrap();
+    assert_eq!(entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["c"]);
+
+    //不是空目录时不能删除
+    let reply = server.parse_operation(request(Operation::RemoveDir, root.clone(), None, None), address.clone()).unwrap();
+    assert_eq!(reply.read_status(), Status::BadRequest);
+    for name in ["a", "b", "c"] {
+        fs::remove_file(format!("{}/{}", root, name))?;
+    }
+    let reply = server.parse_operation(request(Operation::RemoveDir, root.clone(), None, None), address.clone()).unwrap();
+    assert_eq!(reply.read_status(), Status::Ok);
+    let reply = server.parse_operation(request(Operation::ListDir, root, None, None), address).unwrap();
+    assert_eq!(reply.read_status(), Status::NotFound);
+    Ok(())
+}
+
+#[test]
+fn test_export_root() -> io::Result<()> {
+    let root = "test_export_root";
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir_all(format!("{}/sub", root))?;
+    fs::write(format!("{}/a.txt", root), "abc")?;
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    server.set_root(root)?;
+    let address = "127.0.0.1:1".to_string();
+    let mut status = |operation, name:&str, content:Option<&str>| {
+        let request = SerialMessage::new(operation, name.to_string(), None, None, None, content.map(|c| c.as_bytes().to_vec()));
+        server.parse_operation(request, address.clone()).unwrap().read_status()
+    };
+
+    //根目录下的文件按客户端的文件名访问
+    assert_eq!(status(Operation::Read, "a.txt", None), Status::Ok);
+    assert_eq!(status(Operation::Read, "./sub/../a.txt", None), Status::PermissionDenied);
+    assert_eq!(status(Operation::Create, "sub/b.txt", Some("b")), Status::Ok);
+    assert_eq!(fs::read_to_string(format!("{}/sub/b.txt", root))?, "b");
+
+    //..和绝对路径都不能访问根目录外面的文件
+    assert_eq!(status(Operation::Read, "../Cargo.toml", None), Status::PermissionDenied);
+    assert_eq!(status(Operation::Create, "../test_escape.txt", Some("x")), Status::PermissionDenied);
+    assert!(fs::metadata("test_escape.txt").is_err());
+    assert_eq!(status(Operation::Length, "/etc/hostname", None), Status::PermissionDenied);
+    assert_eq!(status(Operation::Rename, "a.txt", Some("../test_escape.txt")), Status::PermissionDenied);
+
+    //指向根目录外面的符号链接和无法解析的符号链接都不能使用
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink("..", format!("{}/escape", root))?;
+        std::os::unix::fs::symlink("missing/dir", format!("{}/dangling", root))?;
+        std::os::unix::fs::symlink("sub", format!("{}/inside", root))?;
+        assert_eq!(status(Operation::Read, "escape/Cargo.toml", None), Status::PermissionDenied);
+        assert_eq!(status(Operation::ListDir, "escape", None), Status::PermissionDenied);
+        assert_eq!(status(Operation::Create, "dangling/x.txt", Some("x")), Status::PermissionDenied);
+        assert_eq!(status(Operation::Read, "inside/b.txt", None), Status::Ok);
+
+        //经过根目录内的符号链接访问的是同一个文件，访问控制不能被绕过
+        assert_eq!(server.resolve("inside/b.txt"), server.resolve("sub/b.txt"));
+        assert_eq!(server.resolve("inside/new.txt"), server.resolve("sub/new.txt"));
+        let create = SerialMessage::new(Operation::Create, "sub/c.txt".to_string(), None, Some(0), None, Some("c".as_bytes().to_vec()));
+        assert_eq!(server.parse_operation(create, address.clone()).unwrap().read_status(), Status::Ok);
+        let read = SerialMessage::new(Operation::Read, "inside/c.txt".to_string(), Some(0), None, None, None);
+        assert_eq!(server.parse_operation(read, "127.0.0.1:2".to_string()).unwrap().read_status(), Status::PermissionDenied);
+    }
+
+    //同一个文件的不同写法记录在同一个路径下，通知时使用客户端自己的文件名
+    let reader = UdpSocket::bind("127.0.0.1:0")?;
+    reader.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let read = SerialMessage::new(Operation::Read, "./a.txt".to_string(), Some(0), None, None, None);
+    assert_eq!(server.parse_operation(read, reader.local_addr()?.to_string()).unwrap().read_status(), Status::Ok);
+    let insert = SerialMessage::new(Operation::Insert, "a.txt".to_string(), Some(0), None, Some("X".as_bytes().to_vec()), None);
+    assert_eq!(server.parse_operation(insert, address).unwrap().read_status(), Status::Ok);
+    let mut buf = [0u8; 1000];
+    let (amt, _) = reader.recv_from(&mut buf)?;
+    let notice = SerialMessage::deserialize(buf[..amt].to_vec()).unwrap();
+    assert_eq!((notice.read_operation(), notice.read_name()), (Operation::Invalidate, "./a.txt".to_string()));
+    fs::remove_dir_all(root)
+}
+
+#[test]
+fn test_access_control() -> io::Result<()> {
+    let (name, new_name) = ("test_acl.txt".to_string(), "test_acl_renamed.txt".to_string());
+    let _ = fs::remove_file(&name);
+    let _ = fs::remove_file(&new_name);
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let (owner, other) = ("127.0.0.1:1".to_string(), "127.0.0.1:2".to_string());
+    let mut status = |operation, name:&str, number, sequence:Option<&str>, content:Option<&str>, client:&String| {
+        let request = SerialMessage::new(operation, name.to_string(), None, number,
+            sequence.map(|s| s.as_bytes().to_vec()), content.map(|c| c.as_bytes().to_vec()));
+        server.parse_operation(request, client.clone()).unwrap().read_status()
+    };
+
+    //其他客户端只能读，所有者可以读写
+    assert_eq!(status(Operation::Create, &name, Some(PERMISSION_READ), Some("secret"), Some("abc"), &owner), Status::Ok);
+    assert_eq!(status(Operation::Read, &name, None, None, None, &other), Status::Ok);
+    assert_eq!(status(Operation::Insert, &name, None, Some("X"), None, &other), Status::PermissionDenied);
+    assert_eq!(status(Operation::Delete, &name, None, None, None, &other), Status::PermissionDenied);
+    assert_eq!(status(Operation::Insert, &name, None, Some("X"), None, &owner), Status::Ok);
+
+    //密码正确后加入访问控制列表，重命名后权限不变
+    assert_eq!(status(Operation::Unlock, &name, Some(PERMISSION_WRITE), None, Some("guess"), &other), Status::PermissionDenied);
+    assert_eq!(status(Operation::Unlock, &name, Some(PERMISSION_WRITE), None, Some("secret"), &other), Status::Ok);
+    assert_eq!(status(Operation::Rename, &name, None, None, Some(&new_name), &other), Status::Ok);
+    assert_eq!(status(Operation::Insert, &new_name, None, Some("Y"), None, &other), Status::Ok);
+    assert_eq!(fs::read_to_string(&new_name)?, "YXabc");
+    assert_eq!(status(Operation::Delete, &new_name, None, None, None, &other), Status::Ok);
+
+    //删除后同名的新文件重新设置访问控制
+    assert_eq!(status(Operation::Create, &new_name, Some(0), None, Some("abc"), &owner), Status::Ok);
+    assert_eq!(status(Operation::Length, &new_name, None, None, None, &other), Status::PermissionDenied);
+    assert_eq!(status(Operation::Callback, &new_name, None, None, None, &other), Status::PermissionDenied);
+    assert_eq!(status(Operation::Unlock, &new_name, None, None, Some(""), &other), Status::PermissionDenied);
+    fs::remove_file(&new_name)
+}
+
+#[test]
+fn test_persist_acls() -> io::Result<()> {
+    let root = "test_persist_acls";
+    let _ = fs::remove_dir_all(root);
+    fs::create_dir(root)?;
+    let acl_file = format!("{}/.acls", root);
+    let (owner, other, friend) = ("127.0.0.1:1".to_string(), "127.0.0.1:2".to_string(), "127.0.0.1:3".to_string());
+    let start = || -> io::Result<Server> {
+        let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+        server.set_root(root)?;
+        server.set_acl_file(&acl_file)?;
+        Ok(server)
+    };
+    let status = |server:&mut Server, operation, name:&str, number, sequence:Option<&str>, content:Option<&str>, client:&String| {
+        let request = SerialMessage::new(operation, name.to_string(), None, number,
+            sequence.map(|s| s.as_bytes().to_vec()), content.map(|c| c.as_bytes().to_vec()));
+        server.parse_operation(request, client.clone()).unwrap()
+    };
+
+    let mut server = start()?;
+    assert_eq!(status(&mut server, Operation::Create, "secret.txt", Some(0), Some("secret"), Some("abc"), &owner).read_status(), Status::Ok);
+    assert_eq!(status(&mut server, Operation::Create, "open.txt", None, None, Some("abc"), &owner).read_status(), Status::Ok);
+    assert_eq!(status(&mut server, Operation::Unlock, "secret.txt", Some(PERMISSION_READ), None, Some("secret"), &friend).read_status(), Status::Ok);
+
+    //没有读权限的文件和保存访问控制的文件都不列出，也不能直接访问
+    let reply = status(&mut server, Operation::ListDir, "", None, None, None, &other);
+    let entries = dir::decode_entries(&reply.read_content()).unwrap();
+    assert_eq!(entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["open.txt"]);
+    assert_eq!(reply.read_number(), Some(1));
+    assert_eq!(status(&mut server, Operation::Read, ".acls", None, None, None, &owner).read_status(), Status::PermissionDenied);
+    let reply = status(&mut server, Operation::ListDir, "", None, None, None, &owner);
+    assert_eq!(reply.read_number(), Some(2));
+
+    //服务器重启后访问控制仍然有效，包括解锁后加入的客户端
+    drop(server);
+    let mut server = start()?;
+    assert_eq!(status(&mut server, Operation::Read, "secret.txt", None, None, None, &other).read_status(), Status::PermissionDenied);
+    assert_eq!(status(&mut server, Operation::Read, "secret.txt", None, None, None, &friend).read_status(), Status::Ok);
+    assert_eq!(status(&mut server, Operation::Insert, "secret.txt", None, Some("X"), None, &friend).read_status(), Status::PermissionDenied);
+    assert_eq!(status(&mut server, Operation::Delete, "secret.txt", None, None, None, &owner).read_status(), Status::Ok);
+    drop(server);
+    assert_eq!(start()?.acls.len(), 1);
+    fs::remove_dir_all(root)
+}
+
+#[test]
+fn test_authentication() -> io::Result<()> {
+    let name = "test_authentication.txt".to_string();
+    let _ = fs::remove_file(&name);
+    let mut server = Server::new("127.0.0.1:0".to_string(), 2, Semantics::AtMostOnce);
+    let mut credentials = Credentials::new();
+    credentials.add_user("alice", b"secret")?;
+    server.set_credentials(credentials);
+    let (home, office) = ("127.0.0.1:1".to_string(), "127.0.0.1:2".to_string());
+    let login = |server:&mut Server, user:&str, password:&str, address:&String| {
+        let request = SerialMessage::new(Operation::Login, user.to_string(), None, None, None, Some(password.as_bytes().to_vec()));
+        server.parse_operation(request, address.clone()).unwrap()
+    };
+    let request = |server:&mut Server, operation, token:Option<u128>, address:&String| {
+        let mut request = SerialMessage::new(operation, name.clone(), None, Some(0), None, Some("abc".as_bytes().to_vec()));
+        if let Some(token) = token {
+            request.set_token(token);
+        }
+        server.parse_operation(request, address.clone()).unwrap().read_status()
+    };
+
+    //没有令牌、密码错误或者用户不存在都不能访问
+    assert_eq!(request(&mut server, Operation::Create, None, &home), Status::PermissionDenied);
+    assert_eq!(login(&mut server, "alice", "guess", &home).read_status(), Status::PermissionDenied);
+    assert_eq!(login(&mut server, "bob", "secret", &home).read_status(), Status::PermissionDenied);
+
+    //保存用户的文件在根目录下时，登录的客户端也不能读取、修改或者覆盖
+    let users = "test_authentication_users.txt";
+    let mut stored = Credentials::new();
+    stored.add_user("alice", b"secret")?;
+    stored.save(users)?;
+    let saved = fs::read_to_string(users)?;
+    server.hide_file(users)?;
+    let token = login(&mut server, "alice", "secret", &home).read_token().unwrap();
+    let mut hidden = |operation, name:&str, content:Option<&str>| {
+        let mut request = SerialMessage::new(operation, name.to_string(), Some(0), None, Some("x".as_bytes().to_vec()), content.map(|c| c.as_bytes().to_vec()));
+        request.set_token(token);
+        server.parse_operation(request, home.clone()).unwrap()
+    };
+    for operation in [Operation::Read, Operation::Insert, Operation::Delete, Operation::Length] {
+        assert_eq!(hidden(operation, users, None).read_status(), Status::PermissionDenied);
+    }
+    assert_eq!(hidden(Operation::Create, "test_authentication_other.txt", Some("x")).read_status(), Status::Ok);
+    assert_eq!(hidden(Operation::Rename, "test_authentication_other.txt", Some(users)).read_status(), Status::PermissionDenied);
+    let listed = dir::decode_entries(&hidden(Operation::ListDir, "", None).read_content()).unwrap();
+    assert!(listed.iter().all(|entry| entry.name != users));
+    assert_eq!(fs::read_to_string(users)?, saved);
+    fs::remove_file("test_authentication_other.txt")?;
+    fs::remove_file(users)?;
+
+    //访问控制按用户名而不是地址，同一个用户在别的地址登录后仍然是所有者
+    let reply = login(&mut server, "alice", "secret", &home);
+    assert_eq!((reply.read_status(), reply.read_number()), (Status::Ok, Some(SESSION_TTL.as_secs())));
+    let token = reply.read_token().unwrap();
+    assert_eq!(request(&mut server, Operation::Create, Some(token), &home), Status::Ok);
+    let other = login(&mut server, "alice", "secret", &office).read_token().unwrap();
+    assert_ne!(token, other);
+    assert_eq!(request(&mut server, Operation::Read, Some(other), &office), Status::Ok);
+
+    //从别的地址伪造的令牌、退出登录和过期的令牌都不能使用
+    assert_eq!(request(&mut server, Operation::Read, Some(token), &office), Status::PermissionDenied);
+    assert_eq!(request(&mut server, Operation::Logout, Some(token), &home), Status::Ok);
+    assert_eq!(request(&mut server, Operation::Read, Some(token), &home), Status::PermissionDenied);
+    server.set_session_ttl(Duration::ZERO);
+    let expired = login(&mut server, "alice", "secret", &home).read_token().unwrap();
+    assert_eq!(request(&mut server, Operation::Read, Some(expired), &home), Status::PermissionDenied);
+    fs::remove_file(&name)
 }